@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use crate::compile_error::*;
+use crate::driver;
+use crate::formula_parser::*;
+use crate::ir::{self, BinaryOp, Function, Instruction};
+use crate::minimal_elf::*;
+use crate::regalloc;
+use crate::validation::*;
+use crate::x86::*;
+
+#[derive(Debug)]
+pub struct Equation {
+    pub name: String,
+    pub tree: ParseNode,
+    pub arguments: Vec<String>,
+}
+
+// Parses and checks every formula. A broken formula doesn't stop the others
+// from being checked, so all the problems come back at once, ordered by position.
+// The equations are only safe to compile if none of the problems is an error.
+// Test vectors come back apart from the equations, they're only compiled into
+// self-checking programs.
+pub fn parse_input_formula(input: &str) -> (Vec<Equation>, Vec<TestVector>, Vec<CompileError>) {
+    let mut definitions = Vec::new();
+    let mut tests = Vec::new();
+    let mut problems = Vec::new();
+    // functions whose body doesn't parse, calls to them aren't checked
+    let mut unparsed = Vec::new();
+
+    for (index, formula) in input.split(';').enumerate() {
+        let formula = formula.trim();
+        // tolerate empty formulas, e.g. after a trailing ';'
+        if formula.is_empty() {
+            continue;
+        }
+        match parse_formula(formula, index) {
+            Ok(Formula::Definition(definition)) => definitions.push(definition),
+            Ok(Formula::Test(test)) => tests.push(test),
+            Err(error) => {
+                unparsed.extend(parse_header(formula, index));
+                problems.push(error);
+            }
+        }
+    }
+
+    problems.append(&mut validate(&definitions, &unparsed));
+    problems.append(&mut validate_test_vectors(&definitions, &tests, &unparsed));
+    problems.sort_by_key(|problem| {
+        let span = problem.span();
+        (span.formula, span.start)
+    });
+
+    let equations = definitions.into_iter().map(|definition| Equation {
+        name: definition.name,
+        tree: definition.body,
+        arguments: definition.arguments,
+    }).collect();
+
+    (equations, tests, problems)
+}
+
+// The equations of a source the tests expect to have no errors.
+#[cfg(test)]
+pub fn parse_valid_formulas(input: &str) -> Vec<Equation> {
+    let (equations, _, problems) = parse_input_formula(input);
+    assert!(problems.iter().all(|problem| problem.is_warning()), "{:?}", problems);
+    equations
+}
+
+// A standalone program running one of the functions the way `entry` says, see
+// driver.rs. Fails when `entry` refers to a function that isn't defined.
+//
+// Nothing is both writable and executable: the headers and constants are
+// mapped read-only, the code read-execute, the scratch memory read-write,
+// each in its own page, and the stack isn't executable.
+pub fn assemble_binary(equations: &[Equation], entry: &driver::Entry) -> Result<Vec<u8>, String> {
+    let section = generate_code_section(equations);
+    let mut elf = ElfFile::new(ET_EXEC, FILE_LOAD_VA);
+    let text = elf.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, Vec::new());
+    let rodata = elf.add_section(".rodata", SHT_PROGBITS, SHF_ALLOC, 8, Vec::new());
+    let data = elf.add_section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 8, vec![0; driver::SCRATCH_SIZE as usize]);
+
+    let mut code = section.code;
+    let entry_point_offset = code.len() as u64;
+    let (mut entry_point, entry_point_size, constants) =
+        driver::entry_point_code(entry, entry_point_offset, &section.functions, text, rodata, data)?;
+    code.append(&mut entry_point.bytes);
+    elf.set_contents(text, code);
+    elf.set_contents(rodata, constants.bytes);
+    for (section, addresses) in [(text, &entry_point.addresses), (rodata, &constants.addresses)] {
+        for &(offset, target, target_offset) in addresses {
+            elf.add_address_patch(section, offset, target, target_offset, 8);
+        }
+    }
+
+    for (program_header_type, flags, sections, includes_headers) in [
+        (PT_LOAD, PF_R, vec![rodata], true),
+        (PT_LOAD, PF_R | PF_X, vec![text], false),
+        (PT_LOAD, PF_R | PF_W, vec![data], false),
+        (PT_GNU_STACK, PF_R | PF_W, vec![], false),
+    ] {
+        elf.add_segment(Segment { program_header_type, flags, sections, includes_headers });
+    }
+    elf.set_entry_point(text, entry_point_offset);
+    elf.add_symbol(driver::ENTRY_POINT_NAME, text, entry_point_offset, entry_point_size);
+    for function in &section.functions {
+        elf.add_symbol(&function.name, text, function.offset, function.size);
+    }
+    Ok(elf.write())
+}
+
+// A relocatable object file: the functions in .text, each one a global symbol,
+// and no entry point, ready for `cc main.c formulas.o` or a Rust `#[link]`.
+// Calls between the functions are left to the linker as relocations, the way
+// an assembler handles calls to global symbols.
+pub fn assemble_object(equations: &[Equation]) -> Vec<u8> {
+    let section = generate_code_section(equations);
+    let mut code = section.code;
+    for call in &section.calls {
+        let start = call.offset as usize;
+        code[start..start + 4].fill(0);
+    }
+
+    let mut elf = ElfFile::new(ET_REL, 0);
+    let text = elf.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, code);
+    // an empty .note.GNU-stack tells the linker the code doesn't need an executable stack
+    elf.add_section(".note.GNU-stack", SHT_PROGBITS, 0, 1, Vec::new());
+    for function in &section.functions {
+        elf.add_symbol(&function.name, text, function.offset, function.size);
+    }
+    for call in &section.calls {
+        // the rel32 is relative to the end of the call instruction, 4 bytes past the operand
+        elf.add_relocation(text, call.offset, &call.function, R_X86_64_PLT32, -4);
+    }
+    elf.write()
+}
+
+// A position independent shared library exporting every function through the
+// dynamic symbol table, ready to be loaded with dlopen. The code only uses
+// relative calls, so it runs wherever the dynamic loader maps it.
+//
+// The headers, hash table and dynamic symbols go in a read-only segment, the
+// code in a read-execute one and the dynamic section in a read-write one.
+pub fn assemble_shared_object(equations: &[Equation], soname: &str) -> Vec<u8> {
+    let section = generate_code_section(equations);
+
+    let mut elf = ElfFile::new(ET_DYN, 0);
+    let dynamic = elf.enable_dynamic_linking(soname);
+    let text = elf.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, section.code);
+    for function in &section.functions {
+        elf.add_symbol(&function.name, text, function.offset, function.size);
+    }
+    for (program_header_type, flags, sections, includes_headers) in [
+        (PT_LOAD, PF_R, vec![dynamic.hash, dynamic.dynsym, dynamic.dynstr], true),
+        (PT_LOAD, PF_R | PF_X, vec![text], false),
+        (PT_LOAD, PF_R | PF_W, vec![dynamic.dynamic], false),
+        (PT_DYNAMIC, PF_R | PF_W, vec![dynamic.dynamic], false),
+        (PT_GNU_STACK, PF_R | PF_W, vec![], false),
+    ] {
+        elf.add_segment(Segment { program_header_type, flags, sections, includes_headers });
+    }
+    elf.write()
+}
+
+// System V AMD64 integer argument registers in order.
+pub const ARGUMENT_REGISTERS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+// The state of the code section while the functions are generated.
+struct Generator {
+    code: Assembly,
+    // the label of every function, to call it before it's generated
+    entries: HashMap<String, Label>,
+    functions: Vec<FunctionSymbol>,
+    calls: Vec<CallSite>,
+}
+
+// Copies a value between any two places, through rax when both are in memory.
+fn move_value(code: &mut Assembly, destination: Operand, source: Operand) {
+    match (destination, source) {
+        _ if destination == source => {}
+        (Operand::Mem(_), Operand::Mem(_)) => {
+            code.mov(Reg::Rax, source);
+            code.mov(destination, Reg::Rax);
+        }
+        _ => code.mov(destination, source),
+    }
+}
+
+// register = register <op> operand, for the operators that have a two-operand
+// form.
+fn operator_code(op: BinaryOp, register: Reg, operand: Operand, code: &mut Assembly) {
+    match op {
+        BinaryOp::Add => code.add(register, operand),
+        BinaryOp::Sub => code.sub(register, operand),
+        BinaryOp::Mul => code.imul(register, operand),
+        BinaryOp::Div | BinaryOp::Rem => unreachable!("division only has the one-operand form"),
+    }
+}
+
+// Computes `destination` = `lhs` <op> `rhs` wherever the allocator put them.
+// The destination may share its register with either operand, since operands
+// used for the last time give it away.
+fn binary_code(op: BinaryOp, destination: Operand, lhs: Operand, rhs: Operand, code: &mut Assembly) {
+    match (op, destination) {
+        // rdx:rax / divisor, leaving the quotient in rax and the remainder in rdx
+        (BinaryOp::Div | BinaryOp::Rem, _) => {
+            code.mov(Reg::Rax, lhs);
+            code.cqo();
+            match rhs {
+                Operand::Imm(_) => {
+                    code.mov(Reg::R11, rhs);
+                    code.idiv(Reg::R11);
+                }
+                _ => code.idiv(rhs),
+            }
+            move_value(code, destination, if op == BinaryOp::Div { Reg::Rax } else { Reg::Rdx }.into());
+        }
+        (BinaryOp::Add | BinaryOp::Mul, Operand::Reg(register)) if rhs == destination => {
+            operator_code(op, register, lhs, code);
+        }
+        (_, Operand::Reg(register)) if rhs != destination => {
+            move_value(code, destination, lhs);
+            operator_code(op, register, rhs, code);
+        }
+        _ => {
+            code.mov(Reg::Rax, lhs);
+            operator_code(op, Reg::Rax, rhs, code);
+            move_value(code, destination, Reg::Rax.into());
+        }
+    }
+}
+
+// Arguments are pushed right to left, then the first six are popped into their
+// registers, which leaves the rest on the stack in the order the callee expects
+// them. Going through the stack means no argument register is overwritten
+// before it's read. A padding slot goes in first when needed so that rsp is
+// 16 byte aligned at the call, as it is everywhere else in the body.
+fn call_code(name: &str, arguments: &[Operand], destination: Operand, generator: &mut Generator) {
+    let stack_arguments = arguments.len().saturating_sub(ARGUMENT_REGISTERS.len());
+    let padding = stack_arguments % 2;
+    if padding == 1 {
+        generator.code.sub(Reg::Rsp, 8);
+    }
+    for &argument in arguments.iter().rev() {
+        generator.code.push(argument);
+    }
+    for &register in ARGUMENT_REGISTERS.iter().take(arguments.len()) {
+        generator.code.pop(register);
+    }
+    // the rel32 operand follows the opcode byte
+    generator.calls.push(CallSite { offset: generator.code.position() + 1, function: name.to_string() });
+    generator.code.call(generator.entries[name]);
+    let arguments_size = (stack_arguments + padding) as i32 * 8;
+    if arguments_size > 0 {
+        generator.code.add(Reg::Rsp, arguments_size);
+    }
+    move_value(&mut generator.code, destination, Reg::Rax.into());
+}
+
+// Functions follow the System V AMD64 calling convention: the first six
+// arguments come in rdi, rsi, rdx, rcx, r8 and r9, the rest on the stack, and
+// the result is returned in rax. The callee-saved registers the allocator hands
+// out are pushed after rbp and popped before returning.
+//
+// rbp + 0x10 + 8*i   stack argument 6 + i
+// rbp                saved rbp
+// rbp - 8*(1 + i)    saved register i
+// rbp - 8*(1 + n + i) stack slot i, n being the number of saved registers
+fn function_to_code(function: &Function, generator: &mut Generator) {
+    let allocation = regalloc::allocate(function);
+    let offset = generator.code.position();
+    generator.code.bind(generator.entries[&function.name]);
+
+    generator.code.push(Reg::Rbp);
+    generator.code.mov(Reg::Rbp, Reg::Rsp);
+    for &register in &allocation.saved {
+        generator.code.push(register);
+    }
+    // keep rsp 16 byte aligned
+    let saved_size = allocation.saved.len() * 8;
+    let frame_size = (saved_size + allocation.slots * 8).next_multiple_of(16) - saved_size;
+    if frame_size > 0 {
+        generator.code.sub(Reg::Rsp, frame_size as i32);
+    }
+    // no argument is moved to the register another one comes in
+    for (i, &register) in ARGUMENT_REGISTERS.iter().take(function.arguments).enumerate() {
+        if let Some(location) = allocation.locations[i] {
+            move_value(&mut generator.code, location, register.into());
+        }
+    }
+
+    for (position, instruction) in function.body.instructions.iter().enumerate() {
+        let destination = match allocation.locations[position] {
+            Some(Operand::Imm(_)) | None => continue,
+            Some(destination) => destination,
+        };
+        match instruction {
+            &Instruction::Const(n) => match destination {
+                Operand::Reg(_) => generator.code.mov(destination, n),
+                _ => {
+                    generator.code.mov(Reg::Rax, n);
+                    generator.code.mov(destination, Reg::Rax);
+                }
+            },
+            Instruction::Arg(_) => {}
+            &Instruction::Neg(operand) => {
+                let register = match destination {
+                    Operand::Reg(register) => register,
+                    _ => Reg::Rax,
+                };
+                move_value(&mut generator.code, register.into(), allocation.location(operand));
+                generator.code.neg(register);
+                move_value(&mut generator.code, destination, register.into());
+            }
+            &Instruction::Binary(op, lhs, rhs) => {
+                binary_code(op, destination, allocation.location(lhs), allocation.location(rhs), &mut generator.code);
+            }
+            Instruction::Call(name, arguments) => {
+                let arguments: Vec<Operand> = arguments.iter().map(|&argument| allocation.location(argument)).collect();
+                call_code(name, &arguments, destination, generator);
+            }
+        }
+    }
+    move_value(&mut generator.code, Reg::Rax.into(), allocation.location(function.body.result));
+
+    if allocation.saved.is_empty() {
+        generator.code.leave();
+    } else {
+        generator.code.lea(Reg::Rsp, Mem(Reg::Rbp, -(saved_size as i32)));
+        for &register in allocation.saved.iter().rev() {
+            generator.code.pop(register);
+        }
+        generator.code.pop(Reg::Rbp);
+    }
+    generator.code.ret();
+
+    let size = generator.code.position() - offset;
+    generator.functions.push(FunctionSymbol { name: function.name.clone(), arguments: function.arguments, offset, size });
+}
+
+// Generates every function one after another, the calls between them going
+// to the labels of the functions.
+pub fn generate_code_section(equations: &[Equation]) -> CodeSection {
+    let functions = ir::lower(equations);
+    let mut generator = Generator { code: Assembly::new(0), entries: HashMap::new(), functions: Vec::new(), calls: Vec::new() };
+    for function in &functions {
+        let entry = generator.code.label();
+        generator.entries.insert(function.name.clone(), entry);
+    }
+    for function in &functions {
+        function_to_code(function, &mut generator);
+    }
+    CodeSection { code: generator.code.finish().bytes, functions: generator.functions, calls: generator.calls }
+}
+
+// Where a generated function ends up relative to the start of the code section.
+#[derive(Debug)]
+pub struct FunctionSymbol {
+    pub name: String,
+    pub arguments: usize,
+    pub offset: u64,
+    pub size: u64,
+}
+
+// The rel32 operand at `offset` of a call to `function`.
+#[derive(Debug)]
+pub struct CallSite {
+    pub offset: u64,
+    pub function: String,
+}
+
+#[derive(Debug)]
+pub struct CodeSection {
+    pub code: Vec<u8>,
+    pub functions: Vec<FunctionSymbol>,
+    pub calls: Vec<CallSite>,
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+
+// Location of a piece of source code.
+// `formula` is the index of the `;` separated formula, `start` and `end` are
+// character columns inside that formula once surrounding whitespace is trimmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub formula: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(formula: usize, start: usize, end: usize) -> Span {
+        Span { formula, start, end }
+    }
+
+    // The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.formula, self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    UnexpectedCharacter { found: char, span: Span },
+    UnexpectedToken { found: String, expected: String, span: Span },
+    UnexpectedEnd { expected: String, span: Span },
+    NumberTooLarge { span: Span },
+    EmptyBody { function: String, span: Span },
+    UnknownArgument { name: String, function: String, span: Span },
+    DuplicateArgument { name: String, function: String, span: Span },
+    DuplicateFunction { name: String, span: Span },
+    LiteralOutOfRange { value: u64, span: Span },
+    UnknownFunction { name: String, span: Span },
+    WrongArgumentCount { function: String, expected: usize, found: usize, span: Span },
+    RecursiveCall { function: String, span: Span },
+    // the only warning, the function still compiles
+    UnusedArgument { name: String, function: String, span: Span },
+}
+
+impl CompileError {
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::UnexpectedCharacter { span, .. }
+            | CompileError::UnexpectedToken { span, .. }
+            | CompileError::UnexpectedEnd { span, .. }
+            | CompileError::NumberTooLarge { span }
+            | CompileError::EmptyBody { span, .. }
+            | CompileError::UnknownArgument { span, .. }
+            | CompileError::DuplicateArgument { span, .. }
+            | CompileError::DuplicateFunction { span, .. }
+            | CompileError::LiteralOutOfRange { span, .. }
+            | CompileError::UnknownFunction { span, .. }
+            | CompileError::WrongArgumentCount { span, .. }
+            | CompileError::RecursiveCall { span, .. }
+            | CompileError::UnusedArgument { span, .. } => *span,
+        }
+    }
+
+    pub fn is_warning(&self) -> bool {
+        matches!(self, CompileError::UnusedArgument { .. })
+    }
+
+    // Format the error together with the line of the offending formula where
+    // the span starts and a caret line under the span:
+    //
+    // error: expected ',' or ')', found '='
+    //  --> formula 1, column 10
+    //   |
+    // 1 | avg(x, y = (x + y)/2
+    //   |          ^
+    //
+    // The location becomes `formula 1, line 2, column 5` for formulas written
+    // on several lines.
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+        let formula: Vec<char> = source.split(';').nth(span.formula).unwrap_or("").trim().chars().collect();
+        let start = span.start.min(formula.len());
+        let line_start = formula[..start].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+        let line_end = formula[start..].iter().position(|&c| c == '\n').map_or(formula.len(), |i| start + i);
+        let line: String = formula[line_start..line_end].iter().collect();
+        let location = match formula.contains(&'\n') {
+            true => format!("line {}, column {}", formula[..line_start].iter().filter(|&&c| c == '\n').count() + 1, start - line_start + 1),
+            false => format!("column {}", start + 1),
+        };
+        // tabs are kept so that the carets line up however wide they're shown
+        let padding: String = formula[line_start..start].iter().map(|&c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let number = (span.formula + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let carets = "^".repeat((span.end.min(line_end) - start).max(1));
+        let level = if self.is_warning() { "warning" } else { "error" };
+        format!("{}: {}\n{}--> formula {}, {}\n{} |\n{} | {}\n{} | {}{}\n",
+                level, self, gutter, number, location,
+                gutter,
+                number, line.trim_end(),
+                gutter, padding, carets)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::UnexpectedCharacter { found, .. } => write!(f, "unexpected character '{}'", found),
+            CompileError::UnexpectedToken { found, expected, .. } => write!(f, "expected {}, found {}", expected, found),
+            CompileError::UnexpectedEnd { expected, .. } => write!(f, "expected {}, found end of formula", expected),
+            CompileError::NumberTooLarge { .. } => write!(f, "number doesn't fit in 64 bits"),
+            CompileError::EmptyBody { function, .. } => write!(f, "`{}` has an empty body", function),
+            CompileError::UnknownArgument { name, function, .. } => write!(f, "`{}` is not an argument of `{}`", name, function),
+            CompileError::DuplicateArgument { name, function, .. } => write!(f, "argument `{}` of `{}` is declared more than once", name, function),
+            CompileError::DuplicateFunction { name, .. } => write!(f, "function `{}` is defined more than once", name),
+            CompileError::LiteralOutOfRange { value, .. } => write!(f, "number {} doesn't fit in a signed 64-bit integer", value),
+            CompileError::UnknownFunction { name, .. } => write!(f, "function `{}` is not defined", name),
+            CompileError::WrongArgumentCount { function, expected, found, .. } =>
+                write!(f, "`{}` takes {} argument(s) but {} were given", function, expected, found),
+            CompileError::RecursiveCall { function, .. } =>
+                write!(f, "`{}` ends up calling itself, which never terminates since formulas have no conditions", function),
+            CompileError::UnusedArgument { name, function, .. } => write!(f, "argument `{}` of `{}` is never used", name, function),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::business_logic::parse_input_formula;
+
+    fn render_first(source: &str) -> String {
+        let (_, _, problems) = parse_input_formula(source);
+        problems[0].render(source)
+    }
+
+    #[test]
+    fn formulas_on_several_lines_show_the_line_of_the_problem() {
+        assert_eq!(render_first("sq(x) = x*x;\navg(x, y) =\n   (x + y) / 2 + z"), "\
+error: `z` is not an argument of `avg`
+ --> formula 2, line 2, column 18
+  |
+2 |    (x + y) / 2 + z
+  |                  ^
+");
+    }
+
+    #[test]
+    fn tabs_before_the_problem_are_kept_under_it() {
+        assert_eq!(render_first("avg(x,\ty) = (x +\ty)/2 + zz"), "\
+error: `zz` is not an argument of `avg`
+ --> formula 1, column 25
+  |
+1 | avg(x,\ty) = (x +\ty)/2 + zz
+  |       \t         \t       ^^
+");
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+
+use crate::business_logic::*;
+use crate::formula_parser::TestVector;
+use crate::minimal_elf::*;
+use crate::x86::*;
+
+// The entry point of generated programs. By default it's called like
+//
+// ./miniout.elf quad 2 1 30 4
+//
+// It looks the function up by name in a table of (name, number of arguments,
+// call stub) entries, parses the arguments as signed 64-bit integers, calls the
+// function through its stub and prints the result as `quad = 68`.
+//
+// With `--entry exit-code:quad(2, 1, 30, 4)` it calls the function with those
+// arguments instead and exits with the result as status.
+//
+// With `--entry stdin:quad` it reads lines of integers separated by spaces, tabs
+// or commas from the standard input, calls the function with every line and
+// prints one result per line.
+//
+// Programs made with `--emit selftest` run every test vector of the source
+// instead, print a PASS or FAIL line for each and exit with status 1 if any failed.
+
+// What the entry point does, chosen with --entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    // call the function named on the command line and print its result
+    CommandLine,
+    // call a function with constant arguments and exit with the result
+    ExitCode { function: String, arguments: Vec<i64> },
+    // call a function with every line of the standard input and print the results
+    Stdin { function: String },
+    // check the results of every test vector
+    SelfTest(Vec<TestVector>),
+}
+
+// Parses the value of --entry: `args`, `exit-code:<function>(<arguments>)` or `stdin:<function>`.
+pub fn parse_entry(value: &str) -> Result<Entry, String> {
+    if value == "args" {
+        return Ok(Entry::CommandLine);
+    }
+    if let Some(function) = value.strip_prefix("stdin:") {
+        return Ok(Entry::Stdin { function: function.trim().to_string() });
+    }
+    let call = value.strip_prefix("exit-code:")
+        .ok_or_else(|| format!("unknown --entry `{}`, expected args, exit-code:<function>(<arguments>) or stdin:<function>", value))?;
+    let (function, arguments) = call.strip_suffix(')').and_then(|call| call.split_once('('))
+        .ok_or_else(|| format!("expected <function>(<arguments>) after exit-code:, found `{}`", call))?;
+    let arguments = if arguments.trim().is_empty() {
+        Vec::new()
+    } else {
+        arguments.split(',')
+            .map(|argument| argument.trim().parse::<i64>()
+                .map_err(|_| format!("argument `{}` of exit-code:{} isn't a signed 64-bit integer", argument.trim(), call)))
+            .collect::<Result<_, _>>()?
+    };
+    Ok(Entry::ExitCode { function: function.trim().to_string(), arguments })
+}
+
+pub const ENTRY_POINT_NAME: &str = "_start";
+
+const USAGE: &str = "usage: <program> <function> <arguments...>\n";
+const UNKNOWN_FUNCTION: &str = "error: no such function\n";
+const WRONG_ARGUMENT_COUNT: &str = "error: wrong number of arguments\n";
+const INVALID_ARGUMENT: &str = "error: arguments must be signed 64-bit integers\n";
+const EQUALS: &str = " = ";
+
+// Bytes of scratch memory the entry point needs in its writable section, enough
+// for the longest 64-bit integer with its sign and a new line.
+pub const SCRATCH_SIZE: u64 = 24;
+
+// Size of a function table entry: name address, number of arguments, stub address.
+const TABLE_ENTRY_SIZE: i32 = 24;
+
+// Appends NUL terminated `text` to `data` and returns its offset.
+fn string(data: &mut Assembly, text: &str) -> u64 {
+    let offset = data.position();
+    data.emit(text.as_bytes());
+    data.emit(b"\0");
+    offset
+}
+
+// Calls a function with its arguments read from an array of 64-bit integers
+// pointed to by rdi, following the System V calling convention both ways.
+pub fn call_stub(code: &mut Assembly, function: &FunctionSymbol) {
+    code.push(Reg::Rbp);
+    code.mov(Reg::Rbp, Reg::Rsp);
+    code.mov(Reg::R10, Reg::Rdi);
+
+    let stack_arguments = function.arguments.saturating_sub(ARGUMENT_REGISTERS.len());
+    if stack_arguments % 2 == 1 {
+        // keep the call aligned
+        code.sub(Reg::Rsp, 8);
+    }
+    for i in (ARGUMENT_REGISTERS.len()..function.arguments).rev() {
+        code.push(Mem(Reg::R10, 8 * i as i32));
+    }
+    for (i, &register) in ARGUMENT_REGISTERS.iter().take(function.arguments).enumerate() {
+        code.mov(register, Mem(Reg::R10, 8 * i as i32));
+    }
+    let target = code.label_at(function.offset);
+    code.call(target);
+
+    code.leave();
+    code.ret();
+}
+
+// Parses the NUL terminated string pointed to by rdi into rax, jumping to
+// `invalid` unless it's an optionally negative decimal number fitting in 64 bits.
+fn parse_integer(code: &mut Assembly, invalid: Label) {
+    let digits = code.label();
+    let next_digit = code.label();
+    let negative_digit = code.label();
+    let digit_added = code.label();
+    let done = code.label();
+
+    code.xor(Reg::Rax, Reg::Rax);
+    code.xor(Reg::Rcx, Reg::Rcx); // set for negative numbers
+    code.cmp_byte(Mem(Reg::Rdi, 0), b'-' as i32);
+    code.jcc(Condition::NotEqual, digits);
+    code.inc(Reg::Rdi);
+    code.mov(Reg::Rcx, 1);
+    code.bind(digits);
+    code.cmp_byte(Mem(Reg::Rdi, 0), 0);
+    code.jcc(Condition::Equal, invalid); // no digits at all
+
+    code.bind(next_digit);
+    code.movzx(Reg::Rdx, Mem(Reg::Rdi, 0));
+    code.test(Reg::Rdx, Reg::Rdx);
+    code.jcc(Condition::Equal, done);
+    code.sub(Reg::Rdx, b'0' as i32);
+    code.cmp(Reg::Rdx, 9);
+    code.jcc(Condition::Above, invalid);
+    code.imul(Reg::Rax, 10);
+    code.jcc(Condition::Overflow, invalid);
+    // negative numbers are accumulated downwards so that i64::MIN fits
+    code.test(Reg::Rcx, Reg::Rcx);
+    code.jcc(Condition::NotEqual, negative_digit);
+    code.add(Reg::Rax, Reg::Rdx);
+    code.jmp(digit_added);
+    code.bind(negative_digit);
+    code.sub(Reg::Rax, Reg::Rdx);
+    code.bind(digit_added);
+    code.jcc(Condition::Overflow, invalid);
+    code.inc(Reg::Rdi);
+    code.jmp(next_digit);
+    code.bind(done);
+}
+
+// Subroutine writing the NUL terminated string pointed to by rsi to `fd`.
+fn print_string_code(code: &mut Assembly, fd: i32) {
+    let measured = code.label();
+    let next_byte = code.label();
+    code.xor(Reg::Rdx, Reg::Rdx);
+    code.bind(next_byte);
+    code.cmp_byte(Indexed(Reg::Rsi, Reg::Rdx, 1, 0), 0);
+    code.jcc(Condition::Equal, measured);
+    code.inc(Reg::Rdx);
+    code.jmp(next_byte);
+    code.bind(measured);
+    code.mov(Reg::Rax, 1); // write
+    code.mov(Reg::Rdi, fd);
+    code.syscall();
+    code.ret();
+}
+
+// Subroutine writing rax to `fd` as a signed decimal number, followed by a new
+// line if `new_line` is set. Digits are produced from the last one into the end
+// of the scratch memory in `data`. Remainders take the sign of rax, so negative
+// numbers are converted without negating them first, which wouldn't work for i64::MIN.
+fn print_integer_code(code: &mut Assembly, data: SectionId, fd: i32, new_line: bool) {
+    let next_digit = code.label();
+    let positive_digit = code.label();
+    let unsigned = code.label();
+    code.load_address(Reg::Rdi, data, SCRATCH_SIZE); // mov rdi, end of the scratch memory
+    if new_line {
+        code.dec(Reg::Rdi);
+        code.mov_byte(Mem(Reg::Rdi, 0), b'\n' as i32);
+    }
+    code.mov(Reg::R8, Reg::Rax);
+    code.mov(Reg::Rcx, 10);
+    code.bind(next_digit);
+    code.cqo();
+    code.idiv(Reg::Rcx);
+    code.test(Reg::Rdx, Reg::Rdx);
+    code.jcc(Condition::NotSign, positive_digit);
+    code.neg(Reg::Rdx);
+    code.bind(positive_digit);
+    code.add(Reg::Rdx, b'0' as i32);
+    code.dec(Reg::Rdi);
+    code.mov_byte(Mem(Reg::Rdi, 0), Reg::Rdx);
+    code.test(Reg::Rax, Reg::Rax);
+    code.jcc(Condition::NotEqual, next_digit);
+    code.test(Reg::R8, Reg::R8);
+    code.jcc(Condition::NotSign, unsigned);
+    code.dec(Reg::Rdi);
+    code.mov_byte(Mem(Reg::Rdi, 0), b'-' as i32);
+    code.bind(unsigned);
+    code.mov(Reg::Rsi, Reg::Rdi);
+    code.load_address(Reg::Rdx, data, SCRATCH_SIZE); // mov rdx, end of the scratch memory
+    code.sub(Reg::Rdx, Reg::Rsi);
+    code.mov(Reg::Rax, 1); // write
+    code.mov(Reg::Rdi, fd);
+    code.syscall();
+    code.ret();
+}
+
+// exit(status)
+fn exit(code: &mut Assembly, status: i32) {
+    code.mov(Reg::Rax, 60); // exit
+    code.mov(Reg::Rdi, status);
+    code.syscall();
+}
+
+// The code of the entry point, placed at `entry_point_offset` of `text` right
+// after the functions, its constants for `rodata` and its scratch memory for `data`.
+// Returns the entry point, its size included, and the constants.
+pub fn entry_point_code(entry: &Entry, entry_point_offset: u64, functions: &[FunctionSymbol],
+                        text: SectionId, rodata: SectionId, data: SectionId) -> Result<(Assembly, u64, Assembly), String> {
+    match entry {
+        Entry::CommandLine => Ok(command_line_entry_point(entry_point_offset, functions, text, rodata, data)),
+        Entry::ExitCode { function, arguments } => {
+            let function = find_function(functions, function)?;
+            if function.arguments != arguments.len() {
+                return Err(format!("`{}` takes {} argument(s) but {} were given to --entry",
+                                   function.name, function.arguments, arguments.len()));
+            }
+            Ok(exit_code_entry_point(entry_point_offset, function, arguments, rodata))
+        }
+        Entry::Stdin { function } => {
+            let function = find_function(functions, function)?;
+            Ok(stdin_entry_point(entry_point_offset, function, rodata, data))
+        }
+        Entry::SelfTest(tests) => {
+            if tests.is_empty() {
+                return Err(String::from("--emit selftest needs test vectors such as `avg(100, 80) == 90` in the source"));
+            }
+            Ok(self_test_entry_point(entry_point_offset, functions, tests, rodata, data))
+        }
+    }
+}
+
+fn find_function<'a>(functions: &'a [FunctionSymbol], name: &str) -> Result<&'a FunctionSymbol, String> {
+    functions.iter().find(|function| function.name == name)
+        .ok_or_else(|| format!("function `{}` of --entry is not defined", name))
+}
+
+// Calls the function of every test vector through its stub and compares the
+// result with the expected one, printing
+//
+// PASS avg(100, 80) = 90
+// FAIL avg(1, 2) = 1, expected 2
+//
+// r12 counts the failures and rbx keeps the result while it's printed.
+fn self_test_entry_point(entry_point_offset: u64, functions: &[FunctionSymbol], tests: &[TestVector], rodata: SectionId, data: SectionId)
+    -> (Assembly, u64, Assembly) {
+    let mut constants = Assembly::new(0);
+    let arguments = tests.iter().map(|test| {
+        let offset = constants.position();
+        for argument in &test.arguments {
+            constants.emit(&argument.to_le_bytes());
+        }
+        offset
+    }).collect::<Vec<_>>();
+
+    let mut code = Assembly::new(entry_point_offset);
+    let print_string = code.label();
+    let print_result = code.label();
+    let print_actual = code.label();
+    let mut stubs = HashMap::new();
+
+    code.xor(Reg::R12, Reg::R12);
+    for (test, &arguments) in tests.iter().zip(&arguments) {
+        let call = format!("{}({})", test.function,
+                           test.arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "));
+        let pass = string(&mut constants, &format!("PASS {} = ", call));
+        let fail = string(&mut constants, &format!("FAIL {} = ", call));
+        let expected = string(&mut constants, &format!(", expected {}\n", test.expected));
+        let failed = code.label();
+        let next = code.label();
+        let stub = *stubs.entry(test.function.as_str()).or_insert_with(|| code.label());
+
+        code.load_address(Reg::Rdi, rodata, arguments); // mov rdi, arguments
+        code.call(stub);
+        code.mov(Reg::Rbx, Reg::Rax);
+        code.mov(Reg::Rcx, test.expected);
+        code.cmp(Reg::Rax, Reg::Rcx);
+        code.jcc(Condition::NotEqual, failed);
+        code.load_address(Reg::Rsi, rodata, pass); // mov rsi, pass
+        code.call(print_string);
+        code.mov(Reg::Rax, Reg::Rbx);
+        code.call(print_result);
+        code.jmp(next);
+        code.bind(failed);
+        code.load_address(Reg::Rsi, rodata, fail); // mov rsi, fail
+        code.call(print_string);
+        code.mov(Reg::Rax, Reg::Rbx);
+        code.call(print_actual);
+        code.load_address(Reg::Rsi, rodata, expected); // mov rsi, expected
+        code.call(print_string);
+        code.inc(Reg::R12);
+        code.bind(next);
+    }
+    // exit(r12 != 0)
+    code.xor(Reg::Rdi, Reg::Rdi);
+    code.test(Reg::R12, Reg::R12);
+    code.setcc(Condition::NotEqual, Reg::Rdi);
+    code.mov(Reg::Rax, 60); // exit
+    code.syscall();
+
+    code.bind(print_string);
+    print_string_code(&mut code, 1);
+    code.bind(print_result);
+    print_integer_code(&mut code, data, 1, true);
+    code.bind(print_actual);
+    print_integer_code(&mut code, data, 1, false);
+    let entry_point_size = code.position() - entry_point_offset;
+
+    for function in functions {
+        if let Some(&stub) = stubs.get(function.name.as_str()) {
+            code.emit(&vec![0x90; (code.position().next_multiple_of(16) - code.position()) as usize]);
+            code.bind(stub);
+            call_stub(&mut code, function);
+        }
+    }
+
+    (code.finish(), entry_point_size, constants)
+}
+
+// Size of the buffer the standard input is read into.
+const READ_BUFFER_SIZE: i32 = 4096;
+
+// Bits of rbx describing what has been read so far.
+const IN_NUMBER: i32 = 1;
+const NEGATIVE: i32 = 2;
+const HAS_DIGITS: i32 = 4;
+const END_OF_INPUT: i32 = 8;
+const LINE_NOT_EMPTY: i32 = 16;
+
+// Reads the standard input line by line, calls `function` with the integers of
+// every line and prints each result on its own line. Lines without any number
+// are skipped, unless the function takes no arguments: then every line counts
+// but an empty one at the very end. Stops at the first line that can't be used,
+// reporting its number on stderr.
+//
+// r12 and r13 are the next and end positions in the read buffer, r14 counts the
+// numbers of the current line, r15 accumulates the current number, rbx holds the
+// state bits and rbp the line number. The arguments of the current line are kept
+// at the bottom of the stack, the read buffer right above them.
+fn stdin_entry_point(entry_point_offset: u64, function: &FunctionSymbol, rodata: SectionId, data: SectionId)
+    -> (Assembly, u64, Assembly) {
+    let mut constants = Assembly::new(0);
+    let line_prefix = string(&mut constants, "error: line ");
+    let expected = string(&mut constants, &format!(": expected {} signed 64-bit integer(s)\n", function.arguments));
+    let read_failed = string(&mut constants, "error: failed to read the standard input\n");
+
+    let arguments_size = (8 * function.arguments as u32).next_multiple_of(16) as i32;
+    let mut code = Assembly::new(entry_point_offset);
+    let next_character = code.label();
+    let buffered = code.label();
+    let end_of_input = code.label();
+    let accumulate = code.label();
+    let negative_digit = code.label();
+    let digit_added = code.label();
+    let minus = code.label();
+    let separator = code.label();
+    let end_of_line = code.label();
+    let line_done = code.label();
+    let finish_number = code.label();
+    let number_finished = code.label();
+    let invalid_line = code.label();
+    let read_error = code.label();
+    let stub = code.label();
+    let print_string = code.label();
+    let print_integer = code.label();
+    let print_line_number = code.label();
+
+    code.sub(Reg::Rsp, arguments_size + READ_BUFFER_SIZE); // arguments and read buffer
+    code.xor(Reg::R12, Reg::R12);
+    code.xor(Reg::R13, Reg::R13);
+    code.xor(Reg::R14, Reg::R14);
+    code.xor(Reg::Rbx, Reg::Rbx);
+    code.mov(Reg::Rbp, 1);
+
+    code.bind(next_character);
+    code.cmp(Reg::R12, Reg::R13);
+    code.jcc(Condition::Below, buffered);
+    // read(0, buffer, READ_BUFFER_SIZE)
+    code.xor(Reg::Rax, Reg::Rax);
+    code.xor(Reg::Rdi, Reg::Rdi);
+    code.lea(Reg::Rsi, Mem(Reg::Rsp, arguments_size));
+    code.mov(Reg::Rdx, READ_BUFFER_SIZE);
+    code.syscall();
+    code.test(Reg::Rax, Reg::Rax);
+    code.jcc(Condition::Sign, read_error);
+    code.jcc(Condition::Equal, end_of_input);
+    code.mov(Reg::R12, Reg::Rsi);
+    code.lea(Reg::R13, Indexed(Reg::Rsi, Reg::Rax, 1, 0));
+
+    code.bind(buffered);
+    code.movzx(Reg::Rax, Mem(Reg::R12, 0));
+    code.inc(Reg::R12);
+    code.or(Reg::Rbx, LINE_NOT_EMPTY);
+    code.cmp(Reg::Rax, b'\n' as i32);
+    code.jcc(Condition::Equal, end_of_line);
+    for character in [b' ', b'\t', b',', b'\r'] {
+        code.cmp(Reg::Rax, character as i32);
+        code.jcc(Condition::Equal, separator);
+    }
+    code.cmp(Reg::Rax, b'-' as i32);
+    code.jcc(Condition::Equal, minus);
+    code.sub(Reg::Rax, b'0' as i32);
+    code.cmp(Reg::Rax, 9);
+    code.jcc(Condition::Above, invalid_line);
+
+    code.test(Reg::Rbx, IN_NUMBER);
+    code.jcc(Condition::NotEqual, accumulate);
+    code.or(Reg::Rbx, IN_NUMBER);
+    code.xor(Reg::R15, Reg::R15);
+    code.bind(accumulate);
+    code.or(Reg::Rbx, HAS_DIGITS);
+    code.imul(Reg::R15, 10);
+    code.jcc(Condition::Overflow, invalid_line);
+    // negative numbers are accumulated downwards so that i64::MIN fits
+    code.test(Reg::Rbx, NEGATIVE);
+    code.jcc(Condition::NotEqual, negative_digit);
+    code.add(Reg::R15, Reg::Rax);
+    code.jmp(digit_added);
+    code.bind(negative_digit);
+    code.sub(Reg::R15, Reg::Rax);
+    code.bind(digit_added);
+    code.jcc(Condition::Overflow, invalid_line);
+    code.jmp(next_character);
+
+    // a minus sign starts a number
+    code.bind(minus);
+    code.test(Reg::Rbx, IN_NUMBER);
+    code.jcc(Condition::NotEqual, invalid_line);
+    code.or(Reg::Rbx, IN_NUMBER | NEGATIVE);
+    code.xor(Reg::R15, Reg::R15);
+    code.jmp(next_character);
+
+    code.bind(separator);
+    code.call(finish_number);
+    code.jmp(next_character);
+
+    code.bind(end_of_input);
+    code.or(Reg::Rbx, END_OF_INPUT);
+    code.bind(end_of_line);
+    code.call(finish_number);
+    if function.arguments > 0 {
+        code.test(Reg::R14, Reg::R14);
+    } else {
+        code.test(Reg::Rbx, LINE_NOT_EMPTY);
+    }
+    code.jcc(Condition::Equal, line_done); // nothing to compute on this line
+    code.cmp(Reg::R14, function.arguments as i32);
+    code.jcc(Condition::NotEqual, invalid_line);
+    code.mov(Reg::Rdi, Reg::Rsp);
+    code.call(stub);
+    code.call(print_integer);
+    code.bind(line_done);
+    code.xor(Reg::R14, Reg::R14);
+    code.and(Reg::Rbx, END_OF_INPUT);
+    code.inc(Reg::Rbp);
+    code.test(Reg::Rbx, END_OF_INPUT);
+    code.jcc(Condition::Equal, next_character);
+    exit(&mut code, 0);
+
+    code.bind(invalid_line);
+    code.load_address(Reg::Rsi, rodata, line_prefix); // mov rsi, line_prefix
+    code.call(print_string);
+    code.mov(Reg::Rax, Reg::Rbp);
+    code.call(print_line_number);
+    code.load_address(Reg::Rsi, rodata, expected); // mov rsi, expected
+    code.call(print_string);
+    exit(&mut code, 1);
+
+    code.bind(read_error);
+    code.load_address(Reg::Rsi, rodata, read_failed); // mov rsi, read_failed
+    code.call(print_string);
+    exit(&mut code, 1);
+
+    // subroutine storing the number just read, if any, as the next argument
+    code.bind(finish_number);
+    code.test(Reg::Rbx, IN_NUMBER);
+    code.jcc(Condition::Equal, number_finished);
+    code.test(Reg::Rbx, HAS_DIGITS);
+    code.jcc(Condition::Equal, invalid_line); // a lone minus sign
+    code.cmp(Reg::R14, function.arguments as i32);
+    code.jcc(Condition::AboveOrEqual, invalid_line); // too many numbers
+    code.mov(Indexed(Reg::Rsp, Reg::R14, 8, 8), Reg::R15); // past the return address
+    code.inc(Reg::R14);
+    code.and(Reg::Rbx, !(IN_NUMBER | NEGATIVE | HAS_DIGITS));
+    code.bind(number_finished);
+    code.ret();
+
+    code.bind(print_string);
+    print_string_code(&mut code, 2);
+    code.bind(print_integer);
+    print_integer_code(&mut code, data, 1, true);
+    code.bind(print_line_number);
+    print_integer_code(&mut code, data, 2, false);
+    let entry_point_size = code.position() - entry_point_offset;
+
+    code.emit(&vec![0x90; (code.position().next_multiple_of(16) - code.position()) as usize]);
+    code.bind(stub);
+    call_stub(&mut code, function);
+
+    (code.finish(), entry_point_size, constants)
+}
+
+// Calls `function` with `arguments` and exits with the result, of which only
+// the low byte makes it to the exit status.
+fn exit_code_entry_point(entry_point_offset: u64, function: &FunctionSymbol, arguments: &[i64], rodata: SectionId)
+    -> (Assembly, u64, Assembly) {
+    let mut constants = Assembly::new(0);
+    for argument in arguments {
+        constants.emit(&argument.to_le_bytes());
+    }
+
+    let mut code = Assembly::new(entry_point_offset);
+    let stub = code.label();
+    code.load_address(Reg::Rdi, rodata, 0); // mov rdi, arguments
+    code.call(stub);
+    code.mov(Reg::Rdi, Reg::Rax);
+    code.mov(Reg::Rax, 60); // exit
+    code.syscall();
+    let entry_point_size = code.position() - entry_point_offset;
+
+    code.emit(&vec![0x90; (code.position().next_multiple_of(16) - code.position()) as usize]);
+    code.bind(stub);
+    call_stub(&mut code, function);
+
+    (code.finish(), entry_point_size, constants)
+}
+
+// Looks the function named by argv[1] up and calls it with argv[2..].
+fn command_line_entry_point(entry_point_offset: u64, functions: &[FunctionSymbol], text: SectionId, rodata: SectionId, data: SectionId)
+    -> (Assembly, u64, Assembly) {
+    let mut constants = Assembly::new(0);
+    let usage = string(&mut constants, USAGE);
+    let unknown_function = string(&mut constants, UNKNOWN_FUNCTION);
+    let wrong_argument_count = string(&mut constants, WRONG_ARGUMENT_COUNT);
+    let invalid_argument = string(&mut constants, INVALID_ARGUMENT);
+    let equals = string(&mut constants, EQUALS);
+    let names = functions.iter().map(|function| string(&mut constants, &function.name)).collect::<Vec<_>>();
+
+    let mut code = Assembly::new(entry_point_offset);
+    let next_entry = code.label();
+    let compare = code.label();
+    let not_this_one = code.label();
+    let found = code.label();
+    let next_argument = code.label();
+    let arguments_parsed = code.label();
+    let usage_error = code.label();
+    let unknown_function_error = code.label();
+    let wrong_argument_count_error = code.label();
+    let invalid_argument_error = code.label();
+    let fail = code.label();
+    let print_string = code.label();
+    let print_integer = code.label();
+
+    // rsp points to argc, followed by the argv pointers
+    code.mov(Reg::Rbx, Reg::Rsp);
+    code.mov(Reg::R12, Mem(Reg::Rbx, 0)); // argc
+    code.cmp(Reg::R12, 2);
+    code.jcc(Condition::Less, usage_error);
+
+    // look argv[1] up in the function table, which ends with a NULL name
+    let table = constants.position().next_multiple_of(8);
+    code.load_address(Reg::R13, rodata, table); // mov r13, table
+    code.bind(next_entry);
+    code.mov(Reg::Rsi, Mem(Reg::R13, 0));
+    code.test(Reg::Rsi, Reg::Rsi);
+    code.jcc(Condition::Equal, unknown_function_error);
+    code.mov(Reg::Rdi, Mem(Reg::Rbx, 16)); // argv[1]
+    code.bind(compare);
+    code.movzx(Reg::Rax, Mem(Reg::Rdi, 0));
+    code.cmp_byte(Mem(Reg::Rsi, 0), Reg::Rax);
+    code.jcc(Condition::NotEqual, not_this_one);
+    code.test(Reg::Rax, Reg::Rax);
+    code.jcc(Condition::Equal, found);
+    code.inc(Reg::Rdi);
+    code.inc(Reg::Rsi);
+    code.jmp(compare);
+    code.bind(not_this_one);
+    code.add(Reg::R13, TABLE_ENTRY_SIZE);
+    code.jmp(next_entry);
+
+    // parse argv[2..] into an array on the stack
+    code.bind(found);
+    code.lea(Reg::Rax, Mem(Reg::R12, -2));
+    code.cmp(Reg::Rax, Mem(Reg::R13, 8));
+    code.jcc(Condition::NotEqual, wrong_argument_count_error);
+    code.shl(Reg::Rax, 3);
+    code.sub(Reg::Rsp, Reg::Rax);
+    code.and(Reg::Rsp, -16);
+    code.xor(Reg::R14, Reg::R14);
+    code.bind(next_argument);
+    code.lea(Reg::Rax, Mem(Reg::R12, -2));
+    code.cmp(Reg::R14, Reg::Rax);
+    code.jcc(Condition::GreaterOrEqual, arguments_parsed);
+    code.mov(Reg::Rdi, Indexed(Reg::Rbx, Reg::R14, 8, 24)); // argv[2 + r14]
+    parse_integer(&mut code, invalid_argument_error);
+    code.mov(Indexed(Reg::Rsp, Reg::R14, 8, 0), Reg::Rax);
+    code.inc(Reg::R14);
+    code.jmp(next_argument);
+
+    code.bind(arguments_parsed);
+    code.mov(Reg::Rdi, Reg::Rsp);
+    code.call_indirect(Mem(Reg::R13, 16));
+
+    code.mov(Reg::Rbx, Reg::Rax);
+    code.mov(Reg::Rsi, Mem(Reg::R13, 0)); // the function name
+    code.call(print_string);
+    code.load_address(Reg::Rsi, rodata, equals); // mov rsi, equals
+    code.call(print_string);
+    code.mov(Reg::Rax, Reg::Rbx);
+    code.call(print_integer);
+    exit(&mut code, 0);
+
+    for (label, message, error) in [
+        (usage_error, usage, USAGE),
+        (unknown_function_error, unknown_function, UNKNOWN_FUNCTION),
+        (wrong_argument_count_error, wrong_argument_count, WRONG_ARGUMENT_COUNT),
+        (invalid_argument_error, invalid_argument, INVALID_ARGUMENT),
+    ] {
+        code.bind(label);
+        code.load_address(Reg::Rsi, rodata, message); // mov rsi, message
+        code.mov(Reg::Rdx, error.len() as i32); // length
+        code.jmp(fail);
+    }
+    code.bind(fail);
+    code.mov(Reg::Rax, 1); // write
+    code.mov(Reg::Rdi, 2);
+    code.syscall();
+    exit(&mut code, 1);
+
+    code.bind(print_string);
+    print_string_code(&mut code, 1);
+    code.bind(print_integer);
+    print_integer_code(&mut code, data, 1, true);
+    let entry_point_size = code.position() - entry_point_offset;
+
+    constants.emit(&vec![0; (table - constants.position()) as usize]);
+    let mut stubs = Vec::new();
+    for function in functions {
+        code.emit(&vec![0x90; (code.position().next_multiple_of(16) - code.position()) as usize]);
+        stubs.push(code.position());
+        call_stub(&mut code, function);
+    }
+    for ((function, &name), &stub) in functions.iter().zip(&names).zip(&stubs) {
+        constants.address(rodata, name);
+        constants.emit(&(function.arguments as u64).to_le_bytes());
+        constants.address(text, stub);
+    }
+    constants.emit(&[0; TABLE_ENTRY_SIZE as usize]);
+
+    (code.finish(), entry_point_size, constants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_parsed() {
+        assert_eq!(parse_entry("args"), Ok(Entry::CommandLine));
+        assert_eq!(parse_entry("exit-code:quad(2, 1, -30, 4)"),
+                   Ok(Entry::ExitCode { function: String::from("quad"), arguments: vec![2, 1, -30, 4] }));
+        assert_eq!(parse_entry("exit-code:seven()"), Ok(Entry::ExitCode { function: String::from("seven"), arguments: vec![] }));
+        assert_eq!(parse_entry("stdin:avg"), Ok(Entry::Stdin { function: String::from("avg") }));
+        assert!(parse_entry("exit-code:avg(1, 9223372036854775808)").is_err());
+        assert!(parse_entry("exit-code:avg").is_err());
+        assert!(parse_entry("exit").is_err());
+    }
+}
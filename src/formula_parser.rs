@@ -0,0 +1,500 @@
+use std::fmt;
+use std::iter::Peekable;
+
+use crate::compile_error::*;
+
+#[derive(Debug, Clone)]
+pub enum GrammarItem {
+    Product,
+    Sum,
+    Sub,
+    Div,
+    Rem,
+    Neg,
+    Number(u64),
+    Paren,
+    Arg(String),
+    // the children are the arguments
+    Call(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseNode {
+    pub children: Vec<ParseNode>,
+    pub entry: GrammarItem,
+    pub span: Span,
+}
+
+impl ParseNode {
+    pub fn new() -> ParseNode {
+        ParseNode {
+            children: Vec::new(),
+            entry: GrammarItem::Paren,
+            span: Span::default(),
+        }
+    }
+}
+
+impl Default for ParseNode {
+    fn default() -> ParseNode {
+        ParseNode::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexItem {
+    Paren(char),
+    Op(char),
+    Num(u64),
+    Ident(String),
+    Comma,
+    Equals,
+    EqualsEquals,
+    // always the last token of a formula, so errors at the end have a place to point to
+    End,
+}
+
+impl fmt::Display for LexItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexItem::Paren(c) | LexItem::Op(c) => write!(f, "'{}'", c),
+            LexItem::Num(n) => write!(f, "number {}", n),
+            LexItem::Ident(name) => write!(f, "`{}`", name),
+            LexItem::Comma => write!(f, "','"),
+            LexItem::Equals => write!(f, "'='"),
+            LexItem::EqualsEquals => write!(f, "'=='"),
+            LexItem::End => write!(f, "end of formula"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub item: LexItem,
+    pub span: Span,
+}
+
+// A whole `name(arguments) = body` formula.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub name_span: Span,
+    pub arguments: Vec<String>,
+    pub argument_spans: Vec<Span>,
+    pub body: ParseNode,
+}
+
+// A `name(arguments) == expected` formula checking the result of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub function: String,
+    pub arguments: Vec<i64>,
+    pub expected: i64,
+    // from the function name to the closing paren
+    pub call_span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Formula {
+    Definition(Definition),
+    Test(TestVector),
+}
+
+fn lex(input: &str, formula: usize) -> Result<Vec<Token>, CompileError> {
+    let mut result = Vec::new();
+    let end = input.chars().count();
+
+    let mut it = input.chars().enumerate().peekable();
+    while let Some(&(start, c)) = it.peek() {
+        let item = match c {
+            '0'..='9' => {
+                match get_number(&mut it) {
+                    Some(n) => LexItem::Num(n),
+                    None => {
+                        let end = it.peek().map_or(end, |&(i, _)| i);
+                        return Err(CompileError::NumberTooLarge { span: Span::new(formula, start, end) });
+                    }
+                }
+            }
+            'A'..='Z' | 'a'..='z' | '_' => {
+                LexItem::Ident(get_identifier(&mut it))
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                it.next();
+                LexItem::Op(c)
+            }
+            '(' | ')' => {
+                it.next();
+                LexItem::Paren(c)
+            }
+            ',' => {
+                it.next();
+                LexItem::Comma
+            }
+            '=' => {
+                it.next();
+                if it.next_if(|&(_, c)| c == '=').is_some() {
+                    LexItem::EqualsEquals
+                } else {
+                    LexItem::Equals
+                }
+            }
+            _ if c.is_whitespace() => {
+                it.next();
+                continue;
+            }
+            _ => {
+                return Err(CompileError::UnexpectedCharacter { found: c, span: Span::new(formula, start, start + 1) });
+            }
+        };
+        let end = it.peek().map_or(end, |&(i, _)| i);
+        result.push(Token { item, span: Span::new(formula, start, end) });
+    }
+    result.push(Token { item: LexItem::End, span: Span::new(formula, end, end) });
+    Ok(result)
+}
+
+// Consumes all the digits even if the number overflows, so the error can cover all of them.
+fn get_number<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> Option<u64> {
+    let mut number = Some(0u64);
+    while let Some(&(_, c)) = iter.peek().filter(|(_, c)| c.is_ascii_digit()) {
+        let digit = c.to_digit(10).expect("The caller should have passed a digit.") as u64;
+        number = number.and_then(|n| n.checked_mul(10)).and_then(|n| n.checked_add(digit));
+        iter.next();
+    }
+    number
+}
+
+fn get_identifier<T: Iterator<Item = (usize, char)>>(iter: &mut Peekable<T>) -> String {
+    let mut identifier = String::new();
+    while let Some(&(_, c)) = iter.peek().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+        identifier.push(c);
+        iter.next();
+    }
+    identifier
+}
+
+fn unexpected(token: &Token, expected: &str) -> CompileError {
+    match token.item {
+        LexItem::End => CompileError::UnexpectedEnd { expected: expected.to_string(), span: token.span },
+        _ => CompileError::UnexpectedToken { found: token.item.to_string(), expected: expected.to_string(), span: token.span },
+    }
+}
+
+fn expect(tokens: &[Token], pos: usize, item: LexItem) -> Result<usize, CompileError> {
+    if tokens[pos].item == item {
+        Ok(pos + 1)
+    } else {
+        Err(unexpected(&tokens[pos], &item.to_string()))
+    }
+}
+
+fn expect_identifier(tokens: &[Token], pos: usize, expected: &str) -> Result<(String, usize), CompileError> {
+    match &tokens[pos].item {
+        LexItem::Ident(name) => Ok((name.clone(), pos + 1)),
+        _ => Err(unexpected(&tokens[pos], expected)),
+    }
+}
+
+// Parses the `formula`-th formula of the input, either a definition or a test
+// vector, the latter being the only formulas with a `==`.
+pub fn parse_formula(input: &str, formula: usize) -> Result<Formula, CompileError> {
+    let tokens = lex(input, formula)?;
+    if tokens.iter().any(|token| token.item == LexItem::EqualsEquals) {
+        parse_test_vector(&tokens).map(Formula::Test)
+    } else {
+        definition(&tokens).map(Formula::Definition)
+    }
+}
+
+// Parses `name(arg1, arg2, ...) = expression`, the `formula`-th formula of the input.
+pub fn parse_definition(input: &str, formula: usize) -> Result<Definition, CompileError> {
+    definition(&lex(input, formula)?)
+}
+
+fn definition(tokens: &[Token]) -> Result<Definition, CompileError> {
+    let (name, pos) = expect_identifier(tokens, 0, "function name")?;
+    let name_span = tokens[0].span;
+    let pos = expect(tokens, pos, LexItem::Paren('('))?;
+    let (arguments, pos) = parse_list(tokens, pos, |tokens, pos| {
+        let (argument, next_pos) = expect_identifier(tokens, pos, "argument name")?;
+        Ok(((argument, tokens[pos].span), next_pos))
+    })?;
+    let (arguments, argument_spans) = arguments.into_iter().unzip();
+    let pos = expect(tokens, pos, LexItem::Equals)?;
+    if tokens[pos].item == LexItem::End {
+        return Err(CompileError::EmptyBody { function: name, span: tokens[pos - 1].span });
+    }
+    let (body, pos) = parse_expr(tokens, pos)?;
+    expect(tokens, pos, LexItem::End)?;
+    Ok(Definition { name, name_span, arguments, argument_spans, body })
+}
+
+// The name of a definition from its `name(arguments) =` alone, for when the
+// rest of the formula doesn't parse. None for test vectors and broken headers.
+pub fn parse_header(input: &str, formula: usize) -> Option<String> {
+    if input.contains("==") {
+        return None;
+    }
+    let tokens = lex(&input[..input.find('=')?], formula).ok()?;
+    let (name, pos) = expect_identifier(&tokens, 0, "function name").ok()?;
+    let pos = expect(&tokens, pos, LexItem::Paren('(')).ok()?;
+    let (_, pos) = parse_list(&tokens, pos, |tokens, pos| expect_identifier(tokens, pos, "argument name")).ok()?;
+    (tokens[pos].item == LexItem::End).then_some(name)
+}
+
+// Parses `name(1, -2, ...) == 3` where every number is a signed 64-bit integer.
+fn parse_test_vector(tokens: &[Token]) -> Result<TestVector, CompileError> {
+    let (function, pos) = expect_identifier(tokens, 0, "function name")?;
+    let pos = expect(tokens, pos, LexItem::Paren('('))?;
+    let (arguments, pos) = parse_list(tokens, pos, parse_integer)?;
+    let call_span = tokens[0].span.to(tokens[pos - 1].span);
+    let pos = expect(tokens, pos, LexItem::EqualsEquals)?;
+    let (expected, pos) = parse_integer(tokens, pos)?;
+    expect(tokens, pos, LexItem::End)?;
+    Ok(TestVector { function, arguments, expected, call_span })
+}
+
+// An optionally negative number literal.
+fn parse_integer(tokens: &[Token], pos: usize) -> Result<(i64, usize), CompileError> {
+    let negative = tokens[pos].item == LexItem::Op('-');
+    let digits = if negative { pos + 1 } else { pos };
+    match tokens[digits].item {
+        LexItem::Num(n) => {
+            let value = if negative { 0i64.checked_sub_unsigned(n) } else { i64::try_from(n).ok() };
+            let value = value.ok_or(CompileError::LiteralOutOfRange { value: n, span: tokens[digits].span })?;
+            Ok((value, digits + 1))
+        }
+        _ => Err(unexpected(&tokens[digits], "integer")),
+    }
+}
+
+// Parses `item, item, ...)` up to and including the closing paren.
+fn parse_list<T, F>(tokens: &[Token], mut pos: usize, mut parse_item: F) -> Result<(Vec<T>, usize), CompileError>
+    where F: FnMut(&[Token], usize) -> Result<(T, usize), CompileError>
+{
+    let mut items = Vec::new();
+    if tokens[pos].item != LexItem::Paren(')') {
+        loop {
+            let (item, next_pos) = parse_item(tokens, pos)?;
+            items.push(item);
+            pos = next_pos;
+            match tokens[pos].item {
+                LexItem::Comma => pos += 1,
+                LexItem::Paren(')') => break,
+                _ => return Err(unexpected(&tokens[pos], "',' or ')'")),
+            }
+        }
+    }
+    Ok((items, pos + 1))
+}
+
+// Binding power of the binary operators, higher binds tighter.
+fn binary_operator(token: &Token) -> Option<(GrammarItem, u8)> {
+    match token.item {
+        LexItem::Op('+') => Some((GrammarItem::Sum, 1)),
+        LexItem::Op('-') => Some((GrammarItem::Sub, 1)),
+        LexItem::Op('*') => Some((GrammarItem::Product, 2)),
+        LexItem::Op('/') => Some((GrammarItem::Div, 2)),
+        LexItem::Op('%') => Some((GrammarItem::Rem, 2)),
+        _ => None,
+    }
+}
+
+fn parse_expr(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), CompileError> {
+    parse_binary(tokens, pos, 1)
+}
+
+// Precedence climbing: fold every operator binding at least as tight as
+// `min_precedence` into the tree built so far. The right operand only takes
+// operators binding strictly tighter, which makes all of them left associative.
+fn parse_binary(tokens: &[Token], pos: usize, min_precedence: u8) -> Result<(ParseNode, usize), CompileError> {
+    let (mut node_expr, mut next_pos) = parse_term(tokens, pos)?;
+    while let Some((entry, precedence)) = binary_operator(&tokens[next_pos]) {
+        if precedence < min_precedence {
+            break;
+        }
+        let (rhs, i) = parse_binary(tokens, next_pos + 1, precedence + 1)?;
+        let mut node = ParseNode::new();
+        node.entry = entry;
+        node.span = node_expr.span.to(rhs.span);
+        node.children.push(node_expr);
+        node.children.push(rhs);
+        node_expr = node;
+        next_pos = i;
+    }
+    Ok((node_expr, next_pos))
+}
+
+fn parse_term(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), CompileError> {
+    let token = &tokens[pos];
+    match &token.item {
+        &LexItem::Num(n) => {
+            let mut node = ParseNode::new();
+            node.entry = GrammarItem::Number(n);
+            node.span = token.span;
+            Ok((node, pos + 1))
+        }
+        LexItem::Ident(name) if tokens[pos + 1].item == LexItem::Paren('(') => {
+            let (arguments, next_pos) = parse_list(tokens, pos + 2, parse_expr)?;
+            let mut call = ParseNode::new();
+            call.entry = GrammarItem::Call(name.clone());
+            call.span = token.span.to(tokens[next_pos - 1].span);
+            call.children = arguments;
+            Ok((call, next_pos))
+        }
+        LexItem::Ident(name) => {
+            let mut node = ParseNode::new();
+            node.entry = GrammarItem::Arg(name.clone());
+            node.span = token.span;
+            Ok((node, pos + 1))
+        }
+        LexItem::Op('-') => {
+            // prefix minus binds tighter than any binary operator: -x * y is (-x) * y
+            let (operand, next_pos) = parse_term(tokens, pos + 1)?;
+            let mut neg = ParseNode::new();
+            neg.entry = GrammarItem::Neg;
+            neg.span = token.span.to(operand.span);
+            neg.children.push(operand);
+            Ok((neg, next_pos))
+        }
+        LexItem::Paren('(') => {
+            let (node, next_pos) = parse_expr(tokens, pos + 1)?;
+            let next_pos = expect(tokens, next_pos, LexItem::Paren(')'))?;
+            let mut paren = ParseNode::new();
+            paren.span = token.span.to(tokens[next_pos - 1].span);
+            paren.children.push(node);
+            Ok((paren, next_pos))
+        }
+        _ => Err(unexpected(token, "number, argument or '('")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parses a single expression, the whole input has to be consumed.
+    fn parse(input: &str) -> Result<ParseNode, CompileError> {
+        let tokens = lex(input, 0)?;
+        let (node, pos) = parse_expr(&tokens, 0)?;
+        expect(&tokens, pos, LexItem::End)?;
+        Ok(node)
+    }
+
+    // Render a tree as an s-expression, parentheses in the source are transparent.
+    fn sexpr(node: &ParseNode) -> String {
+        let child = |i: usize| sexpr(&node.children[i]);
+        match &node.entry {
+            GrammarItem::Sum => format!("(+ {} {})", child(0), child(1)),
+            GrammarItem::Sub => format!("(- {} {})", child(0), child(1)),
+            GrammarItem::Product => format!("(* {} {})", child(0), child(1)),
+            GrammarItem::Div => format!("(/ {} {})", child(0), child(1)),
+            GrammarItem::Rem => format!("(% {} {})", child(0), child(1)),
+            GrammarItem::Neg => format!("(- {})", child(0)),
+            GrammarItem::Paren => child(0),
+            GrammarItem::Number(n) => n.to_string(),
+            GrammarItem::Arg(name) => name.clone(),
+            GrammarItem::Call(name) => {
+                let arguments = (0..node.children.len()).map(child).collect::<Vec<_>>();
+                format!("({}{})", name, arguments.iter().map(|a| format!(" {}", a)).collect::<String>())
+            }
+        }
+    }
+
+    #[test]
+    fn operators_group_by_precedence_and_left_to_right() {
+        let cases = [
+            ("a - b - c", "(- (- a b) c)"),
+            ("a + b - c + d", "(+ (- (+ a b) c) d)"),
+            ("a / b * c", "(* (/ a b) c)"),
+            ("a * b / c % d", "(% (/ (* a b) c) d)"),
+            ("x / 2 + 1", "(+ (/ x 2) 1)"),
+            ("1 + x / 2", "(+ 1 (/ x 2))"),
+            ("a - b * c - d", "(- (- a (* b c)) d)"),
+            ("a * x * x + b * x + c", "(+ (+ (* (* a x) x) (* b x)) c)"),
+            ("(x + y) / 2", "(/ (+ x y) 2)"),
+            ("a - (b - c)", "(- a (- b c))"),
+            ("a / (b / c)", "(/ a (/ b c))"),
+            ("-x + 3", "(+ (- x) 3)"),
+            ("x * -2", "(* x (- 2))"),
+            ("-x * y", "(* (- x) y)"),
+            ("a - -b", "(- a (- b))"),
+            ("--x", "(- (- x))"),
+            ("a % b % c", "(% (% a b) c)"),
+            ("((a))", "a"),
+            ("rate * radius - r_2", "(- (* rate radius) r_2)"),
+            ("_Tmp1 / Tmp2", "(/ _Tmp1 Tmp2)"),
+            ("sq(a) + sq(b)", "(+ (sq a) (sq b))"),
+            ("f() * g(x, y + 1, h(z))", "(* (f) (g x (+ y 1) (h z)))"),
+            ("-f(x) - x", "(- (- (f x)) x)"),
+        ];
+        for (input, expected) in cases {
+            let tree = parse(input).unwrap_or_else(|e| panic!("{}: {}", input, e));
+            assert_eq!(sexpr(&tree), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for input in ["a +", "* a", "a b", "(a + b", "a + b)", "a $ b", "2x", "", "f(x,)", "f(x y)", "f(,)", "f(x"] {
+            assert!(parse(input).is_err(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let cases = [
+            ("avg(x, y = x", CompileError::UnexpectedToken { found: "'='".into(), expected: "',' or ')'".into(), span: Span::new(3, 9, 10) }),
+            ("avg(x, y) (x + y)/2", CompileError::UnexpectedToken { found: "'('".into(), expected: "'='".into(), span: Span::new(3, 10, 11) }),
+            ("avg(x, y) = (x + y", CompileError::UnexpectedEnd { expected: "')'".into(), span: Span::new(3, 18, 18) }),
+            ("avg(x, y) = x $ y", CompileError::UnexpectedCharacter { found: '$', span: Span::new(3, 14, 15) }),
+            ("avg(x, y) = -", CompileError::UnexpectedEnd { expected: "number, argument or '('".into(), span: Span::new(3, 13, 13) }),
+            ("avg(x, y) =", CompileError::EmptyBody { function: "avg".into(), span: Span::new(3, 10, 11) }),
+            ("(x) = x", CompileError::UnexpectedToken { found: "'('".into(), expected: "function name".into(), span: Span::new(3, 0, 1) }),
+            ("f(x) = 99999999999999999999 + x", CompileError::NumberTooLarge { span: Span::new(3, 7, 27) }),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_definition(input, 3).unwrap_err(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn definitions_keep_names_and_arguments() {
+        let definition = parse_definition("quad(x, a, b, c) = a*x*x + b*x + c", 0).unwrap();
+        assert_eq!(definition.name, "quad");
+        assert_eq!(definition.arguments, ["x", "a", "b", "c"]);
+        assert_eq!(definition.name_span, Span::new(0, 0, 4));
+        assert_eq!(definition.argument_spans[3], Span::new(0, 14, 15));
+        assert_eq!(definition.body.span, Span::new(0, 19, 34));
+        assert_eq!(definition.body.children[0].children[1].span, Span::new(0, 27, 30));
+        assert_eq!(sexpr(&definition.body), "(+ (+ (* (* a x) x) (* b x)) c)");
+        assert!(parse_definition("seven() = 7", 0).unwrap().arguments.is_empty());
+    }
+
+    #[test]
+    fn test_vectors_hold_signed_integers() {
+        let test = match parse_formula("quad(2, -1, 30, -9223372036854775808) == -68", 0).unwrap() {
+            Formula::Test(test) => test,
+            Formula::Definition(_) => panic!("parsed as a definition"),
+        };
+        assert_eq!(test, TestVector {
+            function: "quad".into(),
+            arguments: vec![2, -1, 30, i64::MIN],
+            expected: -68,
+            call_span: Span::new(0, 0, 37),
+        });
+        assert!(matches!(parse_formula("seven() == 7", 0), Ok(Formula::Test(_))));
+        assert!(matches!(parse_formula("avg(x, y) = x", 0), Ok(Formula::Definition(_))));
+
+        let cases = [
+            ("avg(1, x) == 2", CompileError::UnexpectedToken { found: "`x`".into(), expected: "integer".into(), span: Span::new(0, 7, 8) }),
+            ("avg(1, 2) == 1 + 1", CompileError::UnexpectedToken { found: "'+'".into(), expected: "end of formula".into(), span: Span::new(0, 15, 16) }),
+            ("avg(1, 2) ==", CompileError::UnexpectedEnd { expected: "integer".into(), span: Span::new(0, 12, 12) }),
+            ("f(9223372036854775808) == 0", CompileError::LiteralOutOfRange { value: 9223372036854775808, span: Span::new(0, 2, 21) }),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_formula(input, 0).unwrap_err(), expected, "input: {}", input);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::business_logic::Equation;
+use crate::formula_parser::*;
+
+// The reference semantics of the language, which the generated code has to match:
+//
+// * values are signed 64-bit integers, `+ - *` and prefix `-` wrap around on overflow
+// * `/` truncates toward zero and `%` takes the sign of the dividend
+// * dividing by zero, and dividing i64::MIN by -1 whose result doesn't fit,
+//   are errors, the generated code raises SIGFPE for both
+//
+// Formulas are expected to have been validated: unknown arguments or functions
+// and wrong argument counts are bugs of the caller.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    DivisionByZero,
+    // i64::MIN / -1 or i64::MIN % -1
+    DivisionOverflow,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::DivisionOverflow => write!(f, "division of {} by -1 overflows", i64::MIN),
+        }
+    }
+}
+
+// Evaluates `node` with the values of the arguments of its function, calling
+// the other `equations` as needed.
+pub fn eval(node: &ParseNode, arguments: &HashMap<String, i64>, equations: &[Equation]) -> Result<i64, EvalError> {
+    let operand = |i: usize| eval(&node.children[i], arguments, equations);
+    match &node.entry {
+        // 2^63 is only allowed under a prefix minus, where it wraps to the right value
+        &GrammarItem::Number(n) => Ok(n as i64),
+        GrammarItem::Arg(name) => Ok(*arguments.get(name).unwrap_or_else(|| panic!("unknown argument {}", name))),
+        GrammarItem::Paren => operand(0),
+        GrammarItem::Neg => Ok(operand(0)?.wrapping_neg()),
+        GrammarItem::Sum => Ok(operand(0)?.wrapping_add(operand(1)?)),
+        GrammarItem::Sub => Ok(operand(0)?.wrapping_sub(operand(1)?)),
+        GrammarItem::Product => Ok(operand(0)?.wrapping_mul(operand(1)?)),
+        GrammarItem::Div => divide(operand(0)?, operand(1)?, i64::checked_div),
+        GrammarItem::Rem => divide(operand(0)?, operand(1)?, i64::checked_rem),
+        GrammarItem::Call(name) => {
+            let values = (0..node.children.len()).map(operand).collect::<Result<Vec<_>, _>>()?;
+            call(name, &values, equations)
+        }
+    }
+}
+
+fn divide(dividend: i64, divisor: i64, operation: fn(i64, i64) -> Option<i64>) -> Result<i64, EvalError> {
+    if divisor == 0 {
+        return Err(EvalError::DivisionByZero);
+    }
+    operation(dividend, divisor).ok_or(EvalError::DivisionOverflow)
+}
+
+// Calls the function `name` with the values of its arguments in order.
+pub fn call(name: &str, values: &[i64], equations: &[Equation]) -> Result<i64, EvalError> {
+    let equation = equations.iter().find(|equation| equation.name == name)
+        .unwrap_or_else(|| panic!("unknown function {}", name));
+    assert_eq!(equation.arguments.len(), values.len(), "wrong number of arguments to {}", name);
+    let arguments = equation.arguments.iter().cloned().zip(values.iter().copied()).collect();
+    eval(&equation.tree, &arguments, equations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business_logic::parse_valid_formulas;
+
+    fn run(source: &str, function: &str, values: &[i64]) -> Result<i64, EvalError> {
+        let equations = parse_valid_formulas(source);
+        call(function, values, &equations)
+    }
+
+    #[test]
+    fn arithmetic_follows_the_language_semantics() {
+        assert_eq!(run("avg(x, y) = (x + y)/2", "avg", &[100, 80]), Ok(90));
+        assert_eq!(run("quad(x, a, b, c) = a*x*x + b*x + c", "quad", &[2, 1, 30, 4]), Ok(68));
+        assert_eq!(run("f(x, y) = x / y", "f", &[-7, 2]), Ok(-3));
+        assert_eq!(run("f(x, y) = x % y", "f", &[-7, 2]), Ok(-1));
+        assert_eq!(run("f(x, y) = x % y", "f", &[7, -2]), Ok(1));
+        assert_eq!(run("f(x) = x + 1", "f", &[i64::MAX]), Ok(i64::MIN));
+        assert_eq!(run("f(x) = x * 2", "f", &[i64::MAX]), Ok(-2));
+        assert_eq!(run("f(x) = -x", "f", &[i64::MIN]), Ok(i64::MIN));
+        assert_eq!(run("f() = -9223372036854775808", "f", &[]), Ok(i64::MIN));
+        assert_eq!(run("f(x) = x - 9223372036854775807", "f", &[-2]), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn calls_pass_their_arguments_in_order() {
+        let source = "hyp2(a, b) = sq(a) + sq(b); sq(x) = x*x; sub(a, b) = a - b; g(x) = sub(hyp2(x, 1), sq(x))";
+        assert_eq!(run(source, "hyp2", &[3, 4]), Ok(25));
+        assert_eq!(run(source, "g", &[7]), Ok(1));
+    }
+
+    #[test]
+    fn division_errors_are_reported() {
+        assert_eq!(run("f(x, y) = x / y", "f", &[1, 0]), Err(EvalError::DivisionByZero));
+        assert_eq!(run("f(x, y) = x % y", "f", &[1, 0]), Err(EvalError::DivisionByZero));
+        assert_eq!(run("f(x, y) = x / y", "f", &[i64::MIN, -1]), Err(EvalError::DivisionOverflow));
+        assert_eq!(run("f(x, y) = x % y", "f", &[i64::MIN, -1]), Err(EvalError::DivisionOverflow));
+        assert_eq!(run("f(x) = 1 + g(x) * 0; g(x) = 1 / x", "f", &[0]), Err(EvalError::DivisionByZero));
+    }
+}
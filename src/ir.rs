@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::business_logic::Equation;
+use crate::formula_parser::*;
+
+// A three-address representation of the functions, between the parse trees
+// and the machine code. Every instruction computes one value, which is never
+// reassigned, and values are numbered after the instruction computing them:
+//
+// avg(x, y) = (x + y)/2
+//
+// avg(2):
+//   v0 = arg 0
+//   v1 = arg 1
+//   v2 = add v0, v1
+//   v3 = const 2
+//   v4 = div v2, v3
+//   ret v4
+//
+// All values are signed 64-bit integers, with the semantics the interpreter
+// defines. There are no conditions, so a function is a single block ending with
+// its result. Since nothing has side effects besides division errors, which all
+// end the same way, values can be computed in any order or more than once.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Const(i64),
+    // the value of the function's argument with this index
+    Arg(usize),
+    Neg(Value),
+    Binary(BinaryOp, Value, Value),
+    Call(String, Vec<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    // the instruction computing value i is instructions[i]
+    pub instructions: Vec<Instruction>,
+    pub result: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub arguments: usize,
+    pub body: Block,
+}
+
+impl Block {
+    pub fn instruction(&self, value: Value) -> &Instruction {
+        &self.instructions[value.0]
+    }
+}
+
+pub fn lower(equations: &[Equation]) -> Vec<Function> {
+    equations.iter().map(lower_function).collect()
+}
+
+// The arguments come first, v0 being the first one, then the body.
+pub fn lower_function(eq: &Equation) -> Function {
+    let mut instructions = (0..eq.arguments.len()).map(Instruction::Arg).collect();
+    let result = lower_node(&eq.tree, eq, &mut instructions);
+    Function { name: eq.name.clone(), arguments: eq.arguments.len(), body: Block { instructions, result } }
+}
+
+// Operands are lowered left to right, before the instruction using them.
+fn lower_node(node: &ParseNode, eq: &Equation, instructions: &mut Vec<Instruction>) -> Value {
+    let operand = |i: usize, instructions: &mut Vec<Instruction>| lower_node(&node.children[i], eq, instructions);
+    let instruction = match &node.entry {
+        GrammarItem::Paren => return operand(0, instructions),
+        GrammarItem::Arg(name) => {
+            let index = eq.arguments.iter().position(|argument| argument == name)
+                .unwrap_or_else(|| panic!("{} is not an argument of {}", name, eq.name));
+            return Value(index);
+        }
+        // 2^63 only comes under a prefix minus, as i64::MIN it negates to itself
+        &GrammarItem::Number(n) => Instruction::Const(n as i64),
+        GrammarItem::Neg => Instruction::Neg(operand(0, instructions)),
+        GrammarItem::Call(name) => {
+            let arguments = (0..node.children.len()).map(|i| operand(i, instructions)).collect();
+            Instruction::Call(name.clone(), arguments)
+        }
+        binary => {
+            let op = match binary {
+                GrammarItem::Sum => BinaryOp::Add,
+                GrammarItem::Sub => BinaryOp::Sub,
+                GrammarItem::Product => BinaryOp::Mul,
+                GrammarItem::Div => BinaryOp::Div,
+                GrammarItem::Rem => BinaryOp::Rem,
+                _ => unreachable!(),
+            };
+            let lhs = operand(0, instructions);
+            let rhs = operand(1, instructions);
+            Instruction::Binary(op, lhs, rhs)
+        }
+    };
+    instructions.push(instruction);
+    Value(instructions.len() - 1)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Const(n) => write!(f, "const {}", n),
+            Instruction::Arg(index) => write!(f, "arg {}", index),
+            Instruction::Neg(value) => write!(f, "neg {}", value),
+            Instruction::Binary(op, lhs, rhs) => {
+                let name = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Sub => "sub",
+                    BinaryOp::Mul => "mul",
+                    BinaryOp::Div => "div",
+                    BinaryOp::Rem => "rem",
+                };
+                write!(f, "{} {}, {}", name, lhs, rhs)
+            }
+            Instruction::Call(function, arguments) => {
+                let arguments = arguments.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(f, "call {}({})", function, arguments.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}({}):", self.name, self.arguments)?;
+        for (i, instruction) in self.body.instructions.iter().enumerate() {
+            writeln!(f, "  {} = {}", Value(i), instruction)?;
+        }
+        writeln!(f, "  ret {}", self.body.result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business_logic::parse_valid_formulas;
+
+    fn lower_source(source: &str) -> String {
+        let equations = parse_valid_formulas(source);
+        lower(&equations).iter().map(Function::to_string).collect()
+    }
+
+    #[test]
+    fn operands_come_before_the_instructions_using_them() {
+        assert_eq!(lower_source("avg(x, y) = (x + y)/2"),
+                   "avg(2):\n  v0 = arg 0\n  v1 = arg 1\n  v2 = add v0, v1\n  v3 = const 2\n  v4 = div v2, v3\n  ret v4\n");
+        assert_eq!(lower_source("f(x) = -(x - -9223372036854775808) % x * x"),
+                   "f(1):\n  v0 = arg 0\n  v1 = const -9223372036854775808\n  v2 = neg v1\n  v3 = sub v0, v2\n  \
+                    v4 = neg v3\n  v5 = rem v4, v0\n  v6 = mul v5, v0\n  ret v6\n");
+    }
+
+    #[test]
+    fn calls_take_values_and_arguments_can_be_returned() {
+        assert_eq!(lower_source("sq(x) = x*x; f(a, b) = sq(b) + sq(1); id(x) = x; k() = 7"),
+                   "sq(1):\n  v0 = arg 0\n  v1 = mul v0, v0\n  ret v1\n\
+                    f(2):\n  v0 = arg 0\n  v1 = arg 1\n  v2 = call sq(v1)\n  v3 = const 1\n  v4 = call sq(v3)\n  v5 = add v2, v4\n  ret v5\n\
+                    id(1):\n  v0 = arg 0\n  ret v0\n\
+                    k(0):\n  v0 = const 7\n  ret v0\n");
+    }
+}
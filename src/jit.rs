@@ -0,0 +1,194 @@
+use std::ffi::c_void;
+use std::io;
+
+use crate::business_logic::*;
+use crate::driver;
+use crate::x86;
+
+// Compiles formulas into the memory of the running process, so they can be
+// called without writing a file:
+//
+// let jit = Jit::from_source("avg(x, y) = (x + y)/2")?;
+// let avg = jit.function("avg").unwrap();
+// assert_eq!(avg.call(&[100, 80]), 90);
+//
+// The code is the same as in the generated files, followed by a call stub per
+// function taking the arguments from an array. It's copied into pages mapped
+// writable, which are then made read-execute, so no memory is ever both.
+//
+// Like the generated programs, dividing by zero or dividing i64::MIN by -1
+// raises SIGFPE, which kills the process unless it's handled.
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, length: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, length: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, length: usize) -> i32;
+}
+
+// Every call stub takes a pointer to the arguments and returns the result.
+type Stub = extern "C" fn(*const i64) -> i64;
+
+pub struct Jit {
+    memory: *mut c_void,
+    length: usize,
+    functions: Vec<(FunctionSymbol, u64)>,
+}
+
+// SAFETY: the mapping is read-execute from the end of `compile` until `drop`,
+// so threads can only read it or run the functions, which keep their state on
+// the calling thread's stack.
+unsafe impl Send for Jit {}
+unsafe impl Sync for Jit {}
+
+// A compiled function, valid as long as the Jit it comes from.
+pub struct JitFunction<'a> {
+    jit: &'a Jit,
+    symbol: &'a FunctionSymbol,
+    stub: u64,
+}
+
+impl Jit {
+    // Parses, checks and compiles `source`, failing with every problem found
+    // in it or if the memory can't be mapped.
+    pub fn from_source(source: &str) -> Result<Jit, crate::Error> {
+        let (equations, _) = crate::parse(source)?;
+        Jit::compile(&equations).map_err(crate::Error::System)
+    }
+
+    // The equations must come from `crate::parse`, or from
+    // `parse_input_formula` without errors. Fails if the memory can't be mapped.
+    pub fn compile(equations: &[Equation]) -> Result<Jit, String> {
+        let section = generate_code_section(equations);
+        let mut code = x86::Assembly::new(0);
+        code.emit(&section.code);
+        let mut functions = Vec::new();
+        for function in section.functions {
+            let stub = code.position();
+            driver::call_stub(&mut code, &function);
+            functions.push((function, stub));
+        }
+        let code = code.finish().bytes;
+
+        // mmap refuses empty mappings
+        let length = code.len().max(1);
+        // SAFETY: a fresh anonymous mapping, filled and protected before anything runs from it
+        unsafe {
+            let memory = mmap(std::ptr::null_mut(), length, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            if memory == MAP_FAILED {
+                return Err(format!("failed to map memory for the code: {}", io::Error::last_os_error()));
+            }
+            let jit = Jit { memory, length, functions };
+            std::ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, code.len());
+            if mprotect(memory, length, PROT_READ | PROT_EXEC) != 0 {
+                return Err(format!("failed to make the code executable: {}", io::Error::last_os_error()));
+            }
+            Ok(jit)
+        }
+    }
+
+    pub fn function(&self, name: &str) -> Option<JitFunction<'_>> {
+        self.functions.iter()
+            .find(|(symbol, _)| symbol.name == name)
+            .map(|(symbol, stub)| JitFunction { jit: self, symbol, stub: *stub })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is ours, and no JitFunction outlives the Jit
+        unsafe {
+            munmap(self.memory, self.length);
+        }
+    }
+}
+
+impl JitFunction<'_> {
+    // Calls the function, panics unless `values` holds one value per argument.
+    pub fn call(&self, values: &[i64]) -> i64 {
+        assert_eq!(values.len(), self.symbol.arguments, "wrong number of arguments to {}", self.symbol.name);
+        // SAFETY: the stub reads exactly `arguments` values from the array
+        unsafe {
+            let stub: Stub = std::mem::transmute(self.jit.memory.add(self.stub as usize));
+            stub(values.as_ptr())
+        }
+    }
+
+    // The function itself, following the System V calling convention, to be
+    // transmuted to e.g. `extern "C" fn(i64, i64) -> i64` for `avg(x, y)`.
+    pub fn address(&self) -> *const c_void {
+        // SAFETY: the offset is inside the mapping
+        unsafe { self.jit.memory.add(self.symbol.offset as usize) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> Jit {
+        let equations = parse_valid_formulas(source);
+        Jit::compile(&equations).unwrap()
+    }
+
+    #[test]
+    fn functions_are_called_with_their_arguments() {
+        let jit = compile("avg(x, y) = (x + y)/2; quad(x, a, b, c) = a*x*x + b*x + c; \
+                           sum8(a, b, c, d, e, f, g, h) = a - b + c - d + e - f + g - h*avg(h, h)");
+        assert_eq!(jit.function("avg").unwrap().call(&[100, 80]), 90);
+        assert_eq!(jit.function("quad").unwrap().call(&[2, 1, 30, 4]), 68);
+        assert_eq!(jit.function("sum8").unwrap().call(&[1, 2, 3, 4, 5, 6, 7, 8]), -60);
+        assert!(jit.function("nope").is_none());
+    }
+
+    #[test]
+    fn values_spilled_or_kept_across_calls_are_intact() {
+        // more sums live at once than there are registers, with and without calls in between
+        let sums = |call: &str| (1..=14).rev().fold(String::from(call), |rhs, i| format!("(a + {}) * ({})", i, rhs));
+        let source = format!("g(x, y) = x - y; deep(a, b) = {}; across(a, b) = {}", sums("b"), sums("g(b, a) % 1000"));
+        let equations = parse_valid_formulas(&source);
+        let jit = compile(&source);
+        for name in ["deep", "across"] {
+            let expected = crate::interpreter::call(name, &[3, -7], &equations).unwrap();
+            assert_eq!(jit.function(name).unwrap().call(&[3, -7]), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn functions_can_be_called_from_other_threads() {
+        let jit = Jit::from_source("avg(x, y) = (x + y)/2").unwrap();
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|i| scope.spawn({
+                let jit = &jit;
+                move || jit.function("avg").unwrap().call(&[i, 10])
+            })).collect();
+            let results: Vec<i64> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+            assert_eq!(results, [5, 5, 6, 6]);
+        });
+        let owner = std::thread::spawn(move || jit.function("avg").unwrap().call(&[100, 80]));
+        assert_eq!(owner.join().unwrap(), 90);
+    }
+
+    #[test]
+    fn sources_with_errors_are_not_compiled() {
+        match Jit::from_source("f(x) = y + g(x)") {
+            Err(error @ crate::Error::Source(_)) => assert_eq!(error.to_string(),
+                "`y` is not an argument of `f`\nfunction `g` is not defined"),
+            Err(error) => panic!("unexpected {:?}", error),
+            Ok(_) => panic!("compiled a source with errors"),
+        }
+    }
+
+    #[test]
+    fn functions_can_be_called_through_their_address() {
+        let jit = compile("sub(a, b) = a - b");
+        let sub: extern "C" fn(i64, i64) -> i64 = unsafe { std::mem::transmute(jit.function("sub").unwrap().address()) };
+        assert_eq!(sub(i64::MIN, 1), i64::MAX);
+    }
+}
@@ -0,0 +1,135 @@
+// Compiles formulas like `avg(x, y) = (x + y)/2` to x86-64 machine code, in
+// ELF files or straight into memory:
+//
+// let artifact = minicomp::compile("avg(x, y) = (x + y)/2", &Emit::Object)?;
+// std::fs::write("formulas.o", artifact.bytes)?;
+//
+// The steps are available on their own too: formula_parser turns text into
+// trees, ir lowers the trees to three-address code, regalloc places its values
+// in registers, business_logic turns that into machine code with the assembler
+// of x86 and into ELF files with the help of minimal_elf and driver,
+// interpreter defines what the results should be, and jit runs the code in the
+// current process.
+
+pub mod compile_error;
+pub mod minimal_elf;
+pub mod formula_parser;
+pub mod business_logic;
+pub mod ir;
+pub mod regalloc;
+pub mod x86;
+mod validation;
+pub mod driver;
+pub mod interpreter;
+pub mod jit;
+
+use business_logic::Equation;
+use compile_error::CompileError;
+use formula_parser::TestVector;
+
+// What kind of file to produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Emit {
+    // a standalone program calling the functions the way the entry says
+    Executable(driver::Entry),
+    // a relocatable object file to link into C or Rust programs
+    Object,
+    // a shared library to dlopen, known to the dynamic loader as `soname`
+    SharedObject { soname: String },
+    // a standalone program checking the results of the test vectors
+    SelfTest,
+}
+
+#[derive(Debug)]
+pub struct Artifact {
+    pub bytes: Vec<u8>,
+    // problems that didn't stop the compilation, such as unused arguments
+    pub warnings: Vec<CompileError>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // everything wrong with the source, warnings included, ordered by position
+    Source(Vec<CompileError>),
+    // the source is fine but doesn't fit the options, e.g. the entry calls an undefined function
+    Options(String),
+    // the system refused something compiling needs, e.g. memory for the JIT
+    System(String),
+}
+
+// One line per error, `CompileError::render` shows them in their context.
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Source(problems) => {
+                let errors = problems.iter().filter(|problem| !problem.is_warning()).map(|problem| problem.to_string());
+                write!(f, "{}", errors.collect::<Vec<_>>().join("\n"))
+            }
+            Error::Options(error) | Error::System(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Parses and checks `source`, returning its functions and warnings when
+// nothing stops them from being compiled or evaluated.
+pub fn parse(source: &str) -> Result<(Vec<Equation>, Vec<CompileError>), Error> {
+    parse_with_tests(source).map(|(equations, _, warnings)| (equations, warnings))
+}
+
+// The functions, test vectors and warnings of a source without errors.
+type Parsed = (Vec<Equation>, Vec<TestVector>, Vec<CompileError>);
+
+fn parse_with_tests(source: &str) -> Result<Parsed, Error> {
+    let (equations, tests, problems) = business_logic::parse_input_formula(source);
+    if problems.iter().any(|problem| !problem.is_warning()) {
+        return Err(Error::Source(problems));
+    }
+    Ok((equations, tests, problems))
+}
+
+// Parses, checks and compiles `source` into the file `emit` asks for.
+pub fn compile(source: &str, emit: &Emit) -> Result<Artifact, Error> {
+    let (equations, tests, warnings) = parse_with_tests(source)?;
+    let bytes = match emit {
+        Emit::Executable(entry) => business_logic::assemble_binary(&equations, entry).map_err(Error::Options)?,
+        Emit::Object => business_logic::assemble_object(&equations),
+        Emit::SharedObject { soname } => business_logic::assemble_shared_object(&equations, soname),
+        Emit::SelfTest => business_logic::assemble_binary(&equations, &driver::Entry::SelfTest(tests))
+            .map_err(Error::Options)?,
+    };
+    Ok(Artifact { bytes, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_returns_the_functions_or_every_problem() {
+        let (equations, warnings) = parse("avg(x, y) = (x + y)/2; unused(x) = 1").unwrap();
+        assert_eq!(equations.iter().map(|equation| equation.name.as_str()).collect::<Vec<_>>(), ["avg", "unused"]);
+        assert!(matches!(warnings[..], [CompileError::UnusedArgument { .. }]));
+        match parse("f(x) = y + g(x)") {
+            Err(Error::Source(problems)) => assert_eq!(problems.len(), 2, "{:?}", problems),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compile_reports_problems_or_returns_the_file() {
+        let artifact = compile("avg(x, y) = (x + y)/2; unused(x) = 1", &Emit::Object).unwrap();
+        assert_eq!(&artifact.bytes[..4], b"\x7fELF");
+        assert!(matches!(artifact.warnings[..], [CompileError::UnusedArgument { .. }]));
+
+        match compile("f(x) = x + y; g(x, x) = x", &Emit::Object) {
+            Err(error @ Error::Source(_)) => assert_eq!(error.to_string(),
+                "`y` is not an argument of `f`\nargument `x` of `g` is declared more than once"),
+            other => panic!("unexpected {:?}", other),
+        }
+        let entry = driver::Entry::Stdin { function: String::from("g") };
+        assert!(matches!(compile("f(x) = x", &Emit::Executable(entry)), Err(Error::Options(_))));
+        assert!(matches!(compile("f(x) = x", &Emit::SelfTest), Err(Error::Options(_))));
+    }
+}
@@ -0,0 +1,170 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use minicomp::business_logic::Equation;
+use minicomp::compile_error::CompileError;
+use minicomp::{driver, interpreter, jit, Emit};
+
+struct Options {
+    emit: Emit,
+    output_file: String,
+    function_definitions: String,
+}
+
+fn parse_arguments(args: &[String]) -> Result<Options, String> {
+    let mut emit = "exe";
+    let mut entry = None;
+    let mut soname = None;
+    let mut positional = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--emit" => {
+                emit = match it.next().map(String::as_str) {
+                    Some(kind @ ("exe" | "obj" | "so" | "selftest")) => kind,
+                    Some(other) => return Err(format!("unknown --emit kind `{}`, expected exe, obj, so or selftest", other)),
+                    None => return Err(String::from("--emit needs a value: exe, obj, so or selftest")),
+                }
+            }
+            "--entry" => {
+                entry = Some(driver::parse_entry(it.next().ok_or("--entry needs a value")?)?);
+            }
+            "--soname" => {
+                soname = Some(it.next().ok_or("--soname needs a value")?.clone());
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let Ok([output_file, function_definitions]) = <[String; 2]>::try_from(positional) else {
+        return Err(String::from("expected an output file and the function definitions"));
+    };
+    // an option the output can't honour would give a different file than asked for
+    if entry.is_some() && emit != "exe" {
+        return Err(format!("--entry only applies to executables, not to --emit {}", emit));
+    }
+    if soname.is_some() && emit != "so" {
+        return Err(format!("--soname only applies to shared libraries, not to --emit {}", emit));
+    }
+    let emit = match emit {
+        "obj" => Emit::Object,
+        "selftest" => Emit::SelfTest,
+        "so" => {
+            // by default the library is known by the name of the file it's written to
+            let soname = soname.unwrap_or_else(|| Path::new(&output_file).file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default());
+            Emit::SharedObject { soname }
+        }
+        _ => Emit::Executable(entry.unwrap_or(driver::Entry::CommandLine)),
+    };
+    Ok(Options { emit, output_file, function_definitions })
+}
+
+fn usage(program: &str) {
+    eprintln!("Usage: {} [--emit exe|obj|so|selftest] [--entry args|exit-code:<function>(<arguments>)|stdin:<function>] [--soname <name>] <output_file> <function_definitions>", program);
+    eprintln!("       {} eval|run <function_definitions> <function> <arguments...>", program);
+}
+
+fn render(problems: &[CompileError], function_definitions: &str) {
+    for problem in problems {
+        eprint!("{}", problem.render(function_definitions));
+    }
+}
+
+// The call `minicomp eval|run <function_definitions> <function> <arguments...>` asks for.
+struct Call {
+    equations: Vec<Equation>,
+    function: String,
+    values: Vec<i64>,
+}
+
+// Also returns the warnings about the formulas.
+fn parse_call(subcommand: &str, args: &[String]) -> Result<(Call, Vec<CompileError>), minicomp::Error> {
+    let [function_definitions, function, values @ ..] = args else {
+        return Err(minicomp::Error::Options(format!("{} expects the function definitions, a function and its arguments", subcommand)));
+    };
+    let (equations, warnings) = minicomp::parse(function_definitions)?;
+    let call = check_call(equations, function, values).map_err(minicomp::Error::Options)?;
+    Ok((call, warnings))
+}
+
+fn check_call(equations: Vec<Equation>, function: &str, values: &[String]) -> Result<Call, String> {
+    let equation = equations.iter().find(|equation| equation.name == function)
+        .ok_or_else(|| format!("function `{}` is not defined", function))?;
+    if equation.arguments.len() != values.len() {
+        return Err(format!("`{}` takes {} argument(s) but {} were given", function, equation.arguments.len(), values.len()));
+    }
+    let values = values.iter()
+        .map(|value| value.parse::<i64>().map_err(|_| format!("argument `{}` isn't a signed 64-bit integer", value)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Call { equations, function: function.to_string(), values })
+}
+
+// `minicomp eval` computes a result with the interpreter instead of compiling,
+// printing it the way the generated programs do.
+fn evaluate(call: &Call) -> Result<String, String> {
+    let result = interpreter::call(&call.function, &call.values, &call.equations).map_err(|error| error.to_string())?;
+    Ok(format!("{} = {}", call.function, result))
+}
+
+// `minicomp run` compiles the formulas in memory and calls the function right
+// away. Division errors raise SIGFPE, as in the generated programs.
+fn run(call: &Call) -> Result<String, String> {
+    let jit = jit::Jit::compile(&call.equations)?;
+    let result = jit.function(&call.function).expect("the function was looked up already").call(&call.values);
+    Ok(format!("{} = {}", call.function, result))
+}
+
+// Reports `error`, showing problems with the formulas in their context, and exits.
+fn fail(error: minicomp::Error, function_definitions: &str) -> ! {
+    match error {
+        minicomp::Error::Source(problems) => render(&problems, function_definitions),
+        minicomp::Error::Options(error) | minicomp::Error::System(error) => eprintln!("error: {}", error),
+    }
+    std::process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let subcommand = match args.get(1).map(String::as_str) {
+        Some("eval") => Some(("eval", evaluate as fn(&Call) -> Result<String, String>)),
+        Some("run") => Some(("run", run as fn(&Call) -> Result<String, String>)),
+        _ => None,
+    };
+    if let Some((name, subcommand)) = subcommand {
+        let function_definitions = args.get(2).map(String::as_str).unwrap_or_default();
+        let output = parse_call(name, &args[2..]).and_then(|(call, warnings)| {
+            render(&warnings, function_definitions);
+            subcommand(&call).map_err(minicomp::Error::Options)
+        });
+        match output {
+            Ok(output) => println!("{}", output),
+            Err(error) => fail(error, function_definitions),
+        }
+        return;
+    }
+
+    let options = match parse_arguments(&args[1..]) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            usage(&args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    let machine_code = match minicomp::compile(&options.function_definitions, &options.emit) {
+        Ok(artifact) => {
+            render(&artifact.warnings, &options.function_definitions);
+            artifact.bytes
+        }
+        Err(error) => fail(error, &options.function_definitions),
+    };
+
+    if let Err(error) = File::create(&options.output_file).and_then(|mut file| file.write_all(&machine_code)) {
+        eprintln!("error: failed to write {}: {}", options.output_file, error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,672 @@
+use serde::{Serialize, Deserialize};
+
+// Virtual address where the file is going to be loaded into. Keep it page-aligned.
+pub const FILE_LOAD_VA: u64 = 4096 * 40;
+
+// ELF file types
+pub const ET_EXEC: u16 = 2;
+pub const ET_REL: u16 = 1;
+pub const ET_DYN: u16 = 3;
+
+// Segment types
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_GNU_STACK: u32 = 0x6474e551;
+
+// Segment flags
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+// Section types
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_HASH: u32 = 5;
+pub const SHT_DYNAMIC: u32 = 6;
+pub const SHT_DYNSYM: u32 = 11;
+
+// Section flags
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+pub const SHF_INFO_LINK: u64 = 0x40;
+
+// st_info of a global function symbol: STB_GLOBAL << 4 | STT_FUNC
+pub const GLOBAL_FUNCTION: u8 = 0x12;
+
+// PC-relative reference to a function, what compilers use for calls
+pub const R_X86_64_PLT32: u32 = 4;
+
+// Dynamic section tags
+pub const DT_NULL: i64 = 0;
+pub const DT_HASH: i64 = 4;
+pub const DT_STRTAB: i64 = 5;
+pub const DT_SYMTAB: i64 = 6;
+pub const DT_STRSZ: i64 = 10;
+pub const DT_SYMENT: i64 = 11;
+pub const DT_SONAME: i64 = 14;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+pub const ELF_HEADER_SIZE: u16 = 64;
+pub const PROGRAM_HEADER_SIZE: u16 = 0x38;
+pub const SECTION_HEADER_SIZE: u16 = 0x40;
+pub const SYMTAB_ENTRY_SIZE: u64 = 0x18;
+pub const RELA_ENTRY_SIZE: u64 = 0x18;
+pub const DYNAMIC_ENTRY_SIZE: u64 = 0x10;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ElfHeader {
+    pub signature: [u8;4],
+    pub class: u8,
+    pub endianness: u8,
+    pub elf_version: u8,
+    pub os_abi: u8,
+    pub extended_abi: u64,
+    pub elf_file_type: u16,
+    pub target_architecture: u16,
+    pub additional_elf_version: u32,
+    pub entry_point: u64,
+    pub program_header_offset: u64,
+    pub section_header_offset: u64,
+    pub flags: u32,
+    pub size_of_elf_header: u16,
+    pub size_of_program_header_entry: u16,
+    pub number_of_program_header_entries: u16,
+    pub size_of_section_header_entry: u16,
+    pub number_of_section_header_entries: u16,
+    pub index_of_string_table: u16,
+}
+
+impl ElfHeader {
+    // A 64-bit little endian x86-64 header of the given type, with no segments nor sections yet.
+    pub fn new(elf_file_type: u16) -> ElfHeader {
+        ElfHeader {
+            signature: *b"\x7fELF",
+            class: 2,
+            endianness: 1,
+            elf_version: 1,
+            os_abi: 0,
+            extended_abi: 0,
+            elf_file_type,
+            target_architecture: 0x3e,
+            additional_elf_version: 1,
+            entry_point: 0,
+            program_header_offset: 0,
+            section_header_offset: 0,
+            flags: 0,
+            size_of_elf_header: ELF_HEADER_SIZE,
+            size_of_program_header_entry: PROGRAM_HEADER_SIZE,
+            number_of_program_header_entries: 0,
+            size_of_section_header_entry: SECTION_HEADER_SIZE,
+            number_of_section_header_entries: 0,
+            index_of_string_table: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProgramHeader {
+    pub program_header_type: u32,
+    pub program_header_flags: u32,
+    pub loadable_segment_offset: u64,
+    pub virtual_address: u64,
+    pub physical_address: u64,
+    pub segment_size_in_file: u64,
+    pub segment_size_in_memory: u64,
+    pub segment_aligment: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub bits: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,  
+}
+
+#[derive(Serialize, Debug)]
+pub struct SymtabEntry {
+	pub	name: u32,
+	pub info: u8,
+	pub other: u8,
+	pub shndx: u16,
+	pub value: u64,
+	pub size: u64,   
+}
+
+#[derive(Serialize, Debug)]
+pub struct RelaEntry {
+    pub offset: u64,
+    pub info: u64,
+    pub addend: i64,
+}
+
+impl RelaEntry {
+    pub fn new(offset: u64, symbol: u32, relocation_type: u32, addend: i64) -> RelaEntry {
+        RelaEntry { offset, info: (symbol as u64) << 32 | relocation_type as u64, addend }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct DynamicEntry {
+    pub tag: i64,
+    pub value: u64,
+}
+
+// The SysV symbol hash used by DT_HASH tables.
+pub fn elf_hash(name: &str) -> u32 {
+    let mut h: u32 = 0;
+    for &c in name.as_bytes() {
+        h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xf000_0000;
+        if g != 0 {
+            h ^= g >> 24;
+        }
+        h &= !g;
+    }
+    h
+}
+
+// A DT_HASH table for symbols 1..=names.len() of the dynamic symbol table, symbol 0 being NULL.
+pub fn hash_table(names: &[&str]) -> Vec<u8> {
+    let nbucket = names.len().max(1);
+    let nchain = names.len() + 1;
+    let mut buckets = vec![0u32; nbucket];
+    let mut chains = vec![0u32; nchain];
+    for (i, name) in names.iter().enumerate() {
+        let symbol = i as u32 + 1;
+        let bucket = elf_hash(name) as usize % nbucket;
+        // prepend the symbol to the chain of its bucket
+        chains[symbol as usize] = buckets[bucket];
+        buckets[bucket] = symbol;
+    }
+    let mut table = Vec::new();
+    for word in [nbucket as u32, nchain as u32].iter().chain(&buckets).chain(&chains) {
+        table.extend_from_slice(&word.to_le_bytes());
+    }
+    table
+}
+
+// Builds a string table out of `names`, returns it with the offset of every name.
+pub fn string_table(names: &[&str]) -> (Vec<u8>, Vec<u32>) {
+    let mut table = vec![0];
+    let mut offsets = Vec::new();
+    for name in names {
+        offsets.push(table.len() as u32);
+        table.extend_from_slice(name.as_bytes());
+        table.push(0);
+    }
+    (table, offsets)
+}
+
+pub fn encode<T: serde::Serialize>(data: T) -> Vec<u8> {
+    let encoded: Vec<u8> = bincode::serialize(&data).unwrap();
+    encoded
+}
+
+// Index of a section in the section header table, 0 being the NULL section.
+pub type SectionId = usize;
+
+// What a section holds. Everything but `Data` is generated from the symbols,
+// relocations and sections of the file while it's being written.
+#[derive(Debug)]
+enum Contents {
+    Data(Vec<u8>),
+    SymbolTable,
+    SymbolNames,
+    SectionNames,
+    // relocations applying to the given section
+    Relocations(SectionId),
+    Hash,
+    DynamicSymbols,
+    DynamicNames,
+    Dynamic,
+}
+
+#[derive(Debug)]
+struct Section {
+    name: String,
+    bits: u32,
+    flags: u64,
+    link: SectionId,
+    info: u32,
+    addralign: u64,
+    entsize: u64,
+    contents: Contents,
+}
+
+// A global function, `value` is relative to the start of its section.
+#[derive(Debug)]
+struct Symbol {
+    name: String,
+    section: SectionId,
+    value: u64,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct Relocation {
+    section: SectionId,
+    offset: u64,
+    symbol: String,
+    relocation_type: u32,
+    addend: i64,
+}
+
+// The absolute address of `target` + `addend`, written `width` bytes wide
+// at `offset` of `section` once the layout is known.
+#[derive(Debug)]
+struct AddressPatch {
+    section: SectionId,
+    offset: u64,
+    target: SectionId,
+    addend: u64,
+    width: usize,
+}
+
+#[derive(Debug)]
+pub struct Segment {
+    pub program_header_type: u32,
+    pub flags: u32,
+    pub sections: Vec<SectionId>,
+    // the segment starts at the beginning of the file and maps the ELF and program headers too
+    pub includes_headers: bool,
+}
+
+// The sections created by ElfFile::enable_dynamic_linking.
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicSections {
+    pub hash: SectionId,
+    pub dynsym: SectionId,
+    pub dynstr: SectionId,
+    pub dynamic: SectionId,
+}
+
+// Where everything ends up, see ElfFile::layout.
+#[derive(Debug, Default)]
+struct Layout {
+    offsets: Vec<u64>,
+    addresses: Vec<u64>,
+    sizes: Vec<u64>,
+    section_header_offset: u64,
+}
+
+// An ELF file under construction. Sections, segments and symbols are added as
+// objects, then `write` computes offsets, addresses, sizes and alignment in a
+// single layout pass and serializes the whole file.
+#[derive(Debug)]
+pub struct ElfFile {
+    file_type: u16,
+    base_address: u64,
+    // index 0 is the NULL section
+    sections: Vec<Section>,
+    segments: Vec<Segment>,
+    symbols: Vec<Symbol>,
+    relocations: Vec<Relocation>,
+    patches: Vec<AddressPatch>,
+    entry_point: Option<(SectionId, u64)>,
+    soname: Option<String>,
+}
+
+impl ElfFile {
+    // `base_address` is the virtual address of the beginning of the file, loadable
+    // sections are mapped at `base_address` + their file offset.
+    pub fn new(file_type: u16, base_address: u64) -> ElfFile {
+        let null = Section {
+            name: String::new(),
+            bits: 0,
+            flags: 0,
+            link: 0,
+            info: 0,
+            addralign: 0,
+            entsize: 0,
+            contents: Contents::Data(Vec::new()),
+        };
+        ElfFile {
+            file_type,
+            base_address,
+            sections: vec![null],
+            segments: Vec::new(),
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+            patches: Vec::new(),
+            entry_point: None,
+            soname: None,
+        }
+    }
+
+    pub fn add_section(&mut self, name: &str, bits: u32, flags: u64, addralign: u64, data: Vec<u8>) -> SectionId {
+        self.push_section(name, bits, flags, addralign, 0, Contents::Data(data))
+    }
+
+    // Replaces the data of a section, for contents that depend on the id of sections added later.
+    pub fn set_contents(&mut self, section: SectionId, data: Vec<u8>) {
+        self.sections[section].contents = Contents::Data(data);
+    }
+
+    fn push_section(&mut self, name: &str, bits: u32, flags: u64, addralign: u64, entsize: u64, contents: Contents) -> SectionId {
+        self.sections.push(Section {
+            name: name.to_string(),
+            bits,
+            flags,
+            link: 0,
+            info: 0,
+            addralign,
+            entsize,
+            contents,
+        });
+        self.sections.len() - 1
+    }
+
+    pub fn add_segment(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    pub fn add_symbol(&mut self, name: &str, section: SectionId, value: u64, size: u64) {
+        self.symbols.push(Symbol { name: name.to_string(), section, value, size });
+    }
+
+    // Only meaningful in relocatable files, where it ends up in a .rela section.
+    pub fn add_relocation(&mut self, section: SectionId, offset: u64, symbol: &str, relocation_type: u32, addend: i64) {
+        self.relocations.push(Relocation { section, offset, symbol: symbol.to_string(), relocation_type, addend });
+    }
+
+    pub fn add_address_patch(&mut self, section: SectionId, offset: u64, target: SectionId, addend: u64, width: usize) {
+        self.patches.push(AddressPatch { section, offset, target, addend, width });
+    }
+
+    pub fn set_entry_point(&mut self, section: SectionId, offset: u64) {
+        self.entry_point = Some((section, offset));
+    }
+
+    // Exports every symbol through a dynamic symbol table, the returned sections
+    // still have to be put in segments by the caller.
+    pub fn enable_dynamic_linking(&mut self, soname: &str) -> DynamicSections {
+        self.soname = Some(soname.to_string());
+        let hash = self.push_section(".hash", SHT_HASH, SHF_ALLOC, 8, 4, Contents::Hash);
+        let dynsym = self.push_section(".dynsym", SHT_DYNSYM, SHF_ALLOC, 8, SYMTAB_ENTRY_SIZE, Contents::DynamicSymbols);
+        let dynstr = self.push_section(".dynstr", SHT_STRTAB, SHF_ALLOC, 1, 0, Contents::DynamicNames);
+        let dynamic = self.push_section(".dynamic", SHT_DYNAMIC, SHF_ALLOC | SHF_WRITE, 8, DYNAMIC_ENTRY_SIZE, Contents::Dynamic);
+        self.sections[hash].link = dynsym;
+        self.sections[dynsym].link = dynstr;
+        // every symbol after the NULL entry is global
+        self.sections[dynsym].info = 1;
+        self.sections[dynamic].link = dynstr;
+        DynamicSections { hash, dynsym, dynstr, dynamic }
+    }
+
+    // Adds the sections generated from the rest of the file: relocations, the
+    // symbol table and the section names, which always come last.
+    fn add_generated_sections(&mut self) {
+        let mut targets = self.relocations.iter().map(|r| r.section).collect::<Vec<_>>();
+        targets.sort();
+        targets.dedup();
+        let relocation_sections = targets.into_iter().map(|target| {
+            let name = format!(".rela{}", self.sections[target].name);
+            let id = self.push_section(&name, SHT_RELA, SHF_INFO_LINK, 8, RELA_ENTRY_SIZE, Contents::Relocations(target));
+            self.sections[id].info = target as u32;
+            id
+        }).collect::<Vec<_>>();
+
+        if !self.symbols.is_empty() {
+            let symtab = self.push_section(".symtab", SHT_SYMTAB, 0, 8, SYMTAB_ENTRY_SIZE, Contents::SymbolTable);
+            let strtab = self.push_section(".strtab", SHT_STRTAB, 0, 1, 0, Contents::SymbolNames);
+            self.sections[symtab].link = strtab;
+            // every symbol after the NULL entry is global
+            self.sections[symtab].info = 1;
+            for id in relocation_sections {
+                self.sections[id].link = symtab;
+            }
+        }
+
+        self.push_section(".shstrtab", SHT_STRTAB, 0, 1, 0, Contents::SectionNames);
+    }
+
+    // Lays the file out in one pass: the ELF header, the program headers, then
+    // the sections of every loadable segment in the order the segments were
+    // added, each segment starting on a new page, then the remaining sections
+    // in order, and finally the section header table.
+    fn layout(&self) -> Layout {
+        // generated contents depend on the layout through addresses only,
+        // never through their size, so they can be measured up front
+        let unplaced = Layout {
+            addresses: vec![0; self.sections.len()],
+            sizes: vec![0; self.sections.len()],
+            ..Layout::default()
+        };
+        let sizes = (0..self.sections.len()).map(|id| self.contents(id, &unplaced).len() as u64).collect::<Vec<_>>();
+
+        let mut offsets = vec![None; self.sections.len()];
+        let mut offset = ELF_HEADER_SIZE as u64 + self.segments.len() as u64 * PROGRAM_HEADER_SIZE as u64;
+        let mut place = |id: SectionId, offset: &mut u64| {
+            if id != 0 && offsets[id].is_none() {
+                *offset = offset.next_multiple_of(self.sections[id].addralign.max(1));
+                offsets[id] = Some(*offset);
+                *offset += sizes[id];
+            }
+        };
+        for segment in self.segments.iter().filter(|s| s.program_header_type == PT_LOAD) {
+            if !segment.includes_headers {
+                offset = offset.next_multiple_of(PAGE_SIZE);
+            }
+            for &id in &segment.sections {
+                place(id, &mut offset);
+            }
+        }
+        for id in 0..self.sections.len() {
+            place(id, &mut offset);
+        }
+
+        let offsets = offsets.into_iter().map(Option::unwrap_or_default).collect::<Vec<_>>();
+        let addresses = offsets.iter().zip(&self.sections).map(|(&offset, section)| {
+            if section.flags & SHF_ALLOC != 0 && self.file_type != ET_REL {
+                self.base_address + offset
+            } else {
+                0
+            }
+        }).collect();
+        Layout { offsets, addresses, sizes, section_header_offset: offset.next_multiple_of(8) }
+    }
+
+    fn symbol_value(&self, symbol: &Symbol, layout: &Layout) -> u64 {
+        layout.addresses[symbol.section] + symbol.value
+    }
+
+    fn symbol_names(&self) -> Vec<&str> {
+        self.symbols.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    fn symbol_table(&self, names: &[u32], layout: &Layout) -> Vec<u8> {
+        let mut table = encode(SymtabEntry { name: 0, info: 0, other: 0, shndx: 0, value: 0, size: 0 });
+        for (symbol, &name) in self.symbols.iter().zip(names) {
+            table.append(&mut encode(SymtabEntry {
+                name,
+                info: GLOBAL_FUNCTION,
+                other: 0,
+                shndx: symbol.section as u16,
+                value: self.symbol_value(symbol, layout),
+                size: symbol.size,
+            }));
+        }
+        table
+    }
+
+    fn contents(&self, id: SectionId, layout: &Layout) -> Vec<u8> {
+        match &self.sections[id].contents {
+            Contents::Data(data) => {
+                let mut data = data.clone();
+                for patch in self.patches.iter().filter(|p| p.section == id) {
+                    let address = (layout.addresses[patch.target] + patch.addend).to_le_bytes();
+                    let start = patch.offset as usize;
+                    data[start..start + patch.width].copy_from_slice(&address[..patch.width]);
+                }
+                data
+            }
+            Contents::SymbolTable => self.symbol_table(&string_table(&self.symbol_names()).1, layout),
+            Contents::SymbolNames => string_table(&self.symbol_names()).0,
+            Contents::SectionNames => {
+                string_table(&self.sections[1..].iter().map(|s| s.name.as_str()).collect::<Vec<_>>()).0
+            }
+            Contents::Relocations(target) => {
+                let mut table = Vec::new();
+                for relocation in self.relocations.iter().filter(|r| r.section == *target) {
+                    let symbol = 1 + self.symbols.iter().position(|s| s.name == relocation.symbol)
+                        .unwrap_or_else(|| panic!("relocation against unknown symbol {}", relocation.symbol));
+                    table.append(&mut encode(RelaEntry::new(relocation.offset, symbol as u32, relocation.relocation_type, relocation.addend)));
+                }
+                table
+            }
+            Contents::Hash => hash_table(&self.symbol_names()),
+            Contents::DynamicSymbols => self.symbol_table(&self.dynamic_names().1, layout),
+            Contents::DynamicNames => self.dynamic_names().0,
+            Contents::Dynamic => {
+                let dynamic = self.dynamic_sections();
+                let (_, names) = self.dynamic_names();
+                let mut table = Vec::new();
+                for (tag, value) in [
+                    (DT_HASH, layout.addresses[dynamic.hash]),
+                    (DT_SYMTAB, layout.addresses[dynamic.dynsym]),
+                    (DT_SYMENT, SYMTAB_ENTRY_SIZE),
+                    (DT_STRTAB, layout.addresses[dynamic.dynstr]),
+                    (DT_STRSZ, layout.sizes[dynamic.dynstr]),
+                    (DT_SONAME, names[self.symbols.len()] as u64),
+                    (DT_NULL, 0),
+                ] {
+                    table.append(&mut encode(DynamicEntry { tag, value }));
+                }
+                table
+            }
+        }
+    }
+
+    // .dynstr holds the symbol names followed by the soname.
+    fn dynamic_names(&self) -> (Vec<u8>, Vec<u32>) {
+        let soname = self.soname.as_deref().unwrap_or_default();
+        string_table(&[self.symbol_names().as_slice(), &[soname]].concat())
+    }
+
+    fn dynamic_sections(&self) -> DynamicSections {
+        let find = |bits| self.sections.iter().position(|s| s.bits == bits).expect("dynamic linking isn't enabled");
+        DynamicSections {
+            hash: find(SHT_HASH),
+            dynsym: find(SHT_DYNSYM),
+            dynstr: self.sections[find(SHT_DYNSYM)].link,
+            dynamic: find(SHT_DYNAMIC),
+        }
+    }
+
+    fn program_header(&self, segment: &Segment, layout: &Layout) -> ProgramHeader {
+        let (start, end) = if segment.program_header_type == PT_GNU_STACK {
+            (0, 0)
+        } else {
+            let start = segment.sections.iter().map(|&id| layout.offsets[id]).min().unwrap_or_default();
+            let end = segment.sections.iter().map(|&id| layout.offsets[id] + layout.sizes[id]).max().unwrap_or(start);
+            (if segment.includes_headers { 0 } else { start }, end)
+        };
+        let segment_aligment = match segment.program_header_type {
+            PT_LOAD => PAGE_SIZE,
+            PT_GNU_STACK => 16,
+            _ => 8,
+        };
+        let address = if segment.program_header_type == PT_GNU_STACK { 0 } else { self.base_address + start };
+        ProgramHeader {
+            program_header_type: segment.program_header_type,
+            program_header_flags: segment.flags,
+            loadable_segment_offset: start,
+            virtual_address: address,
+            physical_address: address,
+            segment_size_in_file: end - start,
+            segment_size_in_memory: end - start,
+            segment_aligment,
+        }
+    }
+
+    pub fn write(mut self) -> Vec<u8> {
+        self.add_generated_sections();
+        let layout = self.layout();
+        let (_, section_names) = string_table(&self.sections[1..].iter().map(|s| s.name.as_str()).collect::<Vec<_>>());
+
+        let mut file = encode(ElfHeader {
+            entry_point: self.entry_point.map_or(0, |(section, offset)| layout.addresses[section] + offset),
+            program_header_offset: if self.segments.is_empty() { 0 } else { ELF_HEADER_SIZE as u64 },
+            section_header_offset: layout.section_header_offset,
+            size_of_program_header_entry: if self.segments.is_empty() { 0 } else { PROGRAM_HEADER_SIZE },
+            number_of_program_header_entries: self.segments.len() as u16,
+            number_of_section_header_entries: self.sections.len() as u16,
+            index_of_string_table: self.sections.len() as u16 - 1,
+            ..ElfHeader::new(self.file_type)
+        });
+        for segment in &self.segments {
+            file.append(&mut encode(self.program_header(segment, &layout)));
+        }
+
+        let mut order = (1..self.sections.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&id| layout.offsets[id]);
+        for id in order {
+            file.resize(layout.offsets[id] as usize, 0);
+            file.append(&mut self.contents(id, &layout));
+        }
+
+        file.resize(layout.section_header_offset as usize, 0);
+        for (id, section) in self.sections.iter().enumerate() {
+            file.append(&mut encode(SectionHeader {
+                name: if id == 0 { 0 } else { section_names[id - 1] },
+                bits: section.bits,
+                flags: section.flags,
+                addr: layout.addresses[id],
+                offset: if id == 0 { 0 } else { layout.offsets[id] },
+                size: layout.sizes[id],
+                link: section.link as u32,
+                info: section.info,
+                addralign: section.addralign,
+                entsize: section.entsize,
+            }));
+        }
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u16(file: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(file[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn read_u64(file: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(file[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn layout_places_segments_on_pages_and_counts_sections() {
+        let mut elf = ElfFile::new(ET_EXEC, FILE_LOAD_VA);
+        let text = elf.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, vec![0xc3; 3]);
+        let data = elf.add_section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 8, vec![0; 8]);
+        elf.add_segment(Segment { program_header_type: PT_LOAD, flags: PF_R | PF_X, sections: vec![text], includes_headers: true });
+        elf.add_segment(Segment { program_header_type: PT_LOAD, flags: PF_R | PF_W, sections: vec![data], includes_headers: false });
+        elf.add_address_patch(data, 0, text, 1, 8);
+        elf.add_symbol("f", text, 1, 2);
+        elf.set_entry_point(text, 1);
+        let file = elf.write();
+
+        let text_offset = ELF_HEADER_SIZE as u64 + 2 * PROGRAM_HEADER_SIZE as u64;
+        assert_eq!(read_u64(&file, 0x18), FILE_LOAD_VA + text_offset + 1);
+        assert_eq!(read_u64(&file, PAGE_SIZE as usize), FILE_LOAD_VA + text_offset + 1);
+        // NULL, .text, .data, .symtab, .strtab, .shstrtab
+        assert_eq!(read_u16(&file, 0x3c), 6);
+        assert_eq!(read_u16(&file, 0x3e), 5);
+        let section_header_offset = read_u64(&file, 0x28) as usize;
+        assert_eq!(section_header_offset % 8, 0);
+        assert_eq!(file.len(), section_header_offset + 6 * SECTION_HEADER_SIZE as usize);
+    }
+}
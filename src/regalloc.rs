@@ -0,0 +1,201 @@
+use crate::business_logic::ARGUMENT_REGISTERS;
+use crate::ir::{Function, Instruction, Value};
+use crate::x86::*;
+
+// Linear scan register allocation over the values of a function.
+//
+// A value lives from the instruction computing it to its last use, arguments
+// from the start of the function, and the result until the return. Values are
+// visited in order and each one gets a free register for its whole life. When
+// none is free, whichever of it and the values holding a register lives the
+// longest goes to a stack slot instead, so that the registers stay with the
+// values needed soonest.
+//
+// A call may overwrite every caller-saved register, so values living across
+// one only get callee-saved registers, which the prologue saves. rax, rdx and
+// r11 are never allocated: the code generator needs them for division, call
+// results and operands that don't fit an instruction.
+//
+// Constants fitting in 32 bits don't need a place, they're immediates, and
+// arguments past the sixth stay where the caller pushed them.
+
+pub const CALLER_SAVED: [Reg; 6] = [Reg::Rcx, Reg::Rsi, Reg::Rdi, Reg::R8, Reg::R9, Reg::R10];
+pub const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+
+#[derive(Debug)]
+pub struct Allocation {
+    // the operand holding every value, None for values that are never used
+    pub locations: Vec<Option<Operand>>,
+    // the callee-saved registers used, pushed right after rbp
+    pub saved: Vec<Reg>,
+    // the stack slots below the saved registers
+    pub slots: usize,
+}
+
+impl Allocation {
+    pub fn location(&self, value: Value) -> Operand {
+        self.locations[value.0].unwrap_or_else(|| panic!("{} is never used", value))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Place {
+    Register(Reg),
+    Slot(usize),
+}
+
+struct Interval {
+    value: Value,
+    end: usize,
+    register: Reg,
+}
+
+fn operands(instruction: &Instruction) -> Vec<Value> {
+    match instruction {
+        Instruction::Const(_) | Instruction::Arg(_) => Vec::new(),
+        &Instruction::Neg(operand) => vec![operand],
+        &Instruction::Binary(_, lhs, rhs) => vec![lhs, rhs],
+        Instruction::Call(_, arguments) => arguments.clone(),
+    }
+}
+
+// Every register argument can stay where it comes in or move to a register
+// that no other argument comes in, so the prologue moves them in any order.
+fn candidates(instruction: &Instruction, across_call: bool) -> Vec<Reg> {
+    if across_call {
+        return CALLEE_SAVED.to_vec();
+    }
+    match *instruction {
+        Instruction::Arg(index) => {
+            let own = ARGUMENT_REGISTERS[index];
+            let others = CALLER_SAVED.iter().filter(|register| !ARGUMENT_REGISTERS.contains(register));
+            CALLER_SAVED.iter().filter(|&&register| register == own).chain(others).chain(&CALLEE_SAVED).copied().collect()
+        }
+        _ => CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect(),
+    }
+}
+
+pub fn allocate(function: &Function) -> Allocation {
+    let instructions = &function.body.instructions;
+    let mut ends = vec![None; instructions.len()];
+    for (position, instruction) in instructions.iter().enumerate() {
+        for operand in operands(instruction) {
+            ends[operand.0] = Some(position);
+        }
+    }
+    ends[function.body.result.0] = Some(instructions.len());
+    let calls: Vec<usize> = instructions.iter().enumerate()
+        .filter(|(_, instruction)| matches!(instruction, Instruction::Call(..)))
+        .map(|(position, _)| position)
+        .collect();
+
+    let mut locations = vec![None; instructions.len()];
+    let mut places = vec![None; instructions.len()];
+    let mut active: Vec<Interval> = Vec::new();
+    let mut slots = 0;
+    for (position, instruction) in instructions.iter().enumerate() {
+        let Some(end) = ends[position] else { continue };
+        let start = match *instruction {
+            Instruction::Const(n) if i32::try_from(n).is_ok() => {
+                locations[position] = Some(Operand::Imm(n));
+                continue;
+            }
+            Instruction::Arg(index) if index >= ARGUMENT_REGISTERS.len() => {
+                let offset = 0x10 + 8 * (index - ARGUMENT_REGISTERS.len()) as i32;
+                locations[position] = Some(Mem(Reg::Rbp, offset).into());
+                continue;
+            }
+            Instruction::Arg(_) => 0,
+            _ => position,
+        };
+
+        // operands used for the last time here can give their register to the result
+        active.retain(|interval| interval.end > start);
+        let across_call = calls.iter().any(|&call| start < call && call < end);
+        let candidates = candidates(instruction, across_call);
+        let free = candidates.iter().find(|&&register| active.iter().all(|interval| interval.register != register));
+        let register = match free {
+            Some(&register) => Some(register),
+            None => {
+                let (index, longest) = active.iter().enumerate()
+                    .filter(|(_, interval)| candidates.contains(&interval.register))
+                    .max_by_key(|(_, interval)| interval.end)
+                    .expect("every value can take a callee-saved register");
+                if longest.end > end {
+                    let spilled = active.remove(index);
+                    places[spilled.value.0] = Some(Place::Slot(slots));
+                    slots += 1;
+                    Some(spilled.register)
+                } else {
+                    None
+                }
+            }
+        };
+        match register {
+            Some(register) => {
+                places[position] = Some(Place::Register(register));
+                active.push(Interval { value: Value(position), end, register });
+            }
+            None => {
+                places[position] = Some(Place::Slot(slots));
+                slots += 1;
+            }
+        }
+    }
+
+    let saved: Vec<Reg> = CALLEE_SAVED.iter().copied()
+        .filter(|&register| places.iter().any(|place| matches!(place, Some(Place::Register(r)) if *r == register)))
+        .collect();
+    for (location, place) in locations.iter_mut().zip(places) {
+        match place {
+            Some(Place::Register(register)) => *location = Some(register.into()),
+            Some(Place::Slot(slot)) => *location = Some(Mem(Reg::Rbp, -8 * (saved.len() + slot + 1) as i32).into()),
+            None => {}
+        }
+    }
+    Allocation { locations, saved, slots }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business_logic::parse_valid_formulas;
+    use crate::ir;
+
+    fn allocate_source(source: &str) -> Vec<Allocation> {
+        let equations = parse_valid_formulas(source);
+        ir::lower(&equations).iter().map(allocate).collect()
+    }
+
+    #[test]
+    fn values_share_registers_once_they_are_dead() {
+        let allocations = allocate_source("f(x, y, z) = (x + y) * (z - 1) / 3");
+        let f = &allocations[0];
+        // v0 = arg 0, v1 = arg 1, v2 = arg 2, v3 = add, v4 = const 1, v5 = sub, v6 = mul, v7 = const 3, v8 = div
+        assert_eq!(f.locations[0], Some(Reg::Rdi.into()));
+        assert_eq!(f.locations[1], Some(Reg::Rsi.into()));
+        // rdx is kept for division
+        assert_eq!(f.locations[2], Some(Reg::R10.into()));
+        assert_eq!(f.locations[3], Some(Reg::Rcx.into()));
+        assert_eq!(f.locations[4], Some(Operand::Imm(1)));
+        assert_eq!(f.locations[5], Some(Reg::Rsi.into()));
+        assert_eq!(f.locations[6], Some(Reg::Rcx.into()));
+        assert_eq!(f.locations[8], Some(Reg::Rcx.into()));
+        assert!(f.saved.is_empty());
+        assert_eq!(f.slots, 0);
+    }
+
+    #[test]
+    fn values_living_across_calls_are_saved_or_spilled() {
+        let allocations = allocate_source("g(x) = x; \
+            f(a, b) = (a + 1) * ((a + 2) * ((a + 3) * ((a + 4) * ((a + 5) * ((a + 6) * g(b))))))");
+        let f = &allocations[1];
+        // the six sums are needed after the call, a and b aren't
+        assert_eq!(f.locations[0], Some(Reg::Rdi.into()));
+        assert_eq!(f.locations[1], Some(Reg::Rsi.into()));
+        assert_eq!(f.saved, CALLEE_SAVED);
+        assert_eq!(f.slots, 1);
+        // a + 1 lives the longest, so it's the one spilled
+        assert_eq!(f.locations[3], Some(Mem(Reg::Rbp, -48).into()));
+    }
+}
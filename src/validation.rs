@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compile_error::*;
+use crate::formula_parser::*;
+
+// Checks that need the parsed formulas rather than their text. Every problem
+// is collected instead of stopping at the first one, so they can all be
+// reported at once. Calls to the `unparsed` functions, whose definition has a
+// header but no body that parses, aren't checked: their error is reported already.
+pub fn validate(definitions: &[Definition], unparsed: &[String]) -> Vec<CompileError> {
+    let mut problems = Vec::new();
+    let mut functions = HashMap::new();
+    for definition in definitions {
+        functions.entry(definition.name.as_str()).or_insert(definition);
+    }
+
+    let mut defined = HashSet::new();
+    for definition in definitions {
+        if !defined.insert(definition.name.as_str()) {
+            problems.push(CompileError::DuplicateFunction {
+                name: definition.name.clone(),
+                span: definition.name_span,
+            });
+        }
+
+        let mut used = HashSet::new();
+        check_body(&definition.body, definition, &functions, unparsed, false, &mut used, &mut problems);
+
+        for (callee, span) in calls(&definition.body) {
+            if functions.contains_key(callee) && reaches(callee, &definition.name, &functions, &mut HashSet::new()) {
+                problems.push(CompileError::RecursiveCall { function: callee.to_string(), span });
+            }
+        }
+
+        let mut declared = HashSet::new();
+        for (argument, &span) in definition.arguments.iter().zip(&definition.argument_spans) {
+            if !declared.insert(argument.as_str()) {
+                problems.push(CompileError::DuplicateArgument {
+                    name: argument.clone(),
+                    function: definition.name.clone(),
+                    span,
+                });
+            } else if !used.contains(argument.as_str()) {
+                problems.push(CompileError::UnusedArgument {
+                    name: argument.clone(),
+                    function: definition.name.clone(),
+                    span,
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+// Checks that test vectors call defined functions with the right number of arguments.
+pub fn validate_test_vectors(definitions: &[Definition], tests: &[TestVector], unparsed: &[String]) -> Vec<CompileError> {
+    let mut problems = Vec::new();
+    for test in tests.iter().filter(|test| !unparsed.contains(&test.function)) {
+        match definitions.iter().find(|definition| definition.name == test.function) {
+            None => problems.push(CompileError::UnknownFunction { name: test.function.clone(), span: test.call_span }),
+            Some(definition) if definition.arguments.len() != test.arguments.len() => {
+                problems.push(CompileError::WrongArgumentCount {
+                    function: test.function.clone(),
+                    expected: definition.arguments.len(),
+                    found: test.arguments.len(),
+                    span: test.call_span,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+// `negated` is set for the operand of a prefix minus, the only place where
+// 9223372036854775808 is allowed since -9223372036854775808 is still in range.
+fn check_body<'a>(node: &'a ParseNode, definition: &Definition, functions: &HashMap<&str, &Definition>, unparsed: &[String],
+                  negated: bool, used: &mut HashSet<&'a str>, problems: &mut Vec<CompileError>) {
+    match &node.entry {
+        &GrammarItem::Number(value) if value > i64::MAX as u64 && !(negated && value == i64::MIN.unsigned_abs()) => {
+            problems.push(CompileError::LiteralOutOfRange { value, span: node.span });
+        }
+        GrammarItem::Arg(name) => {
+            if definition.arguments.contains(name) {
+                used.insert(name);
+            } else {
+                problems.push(CompileError::UnknownArgument {
+                    name: name.clone(),
+                    function: definition.name.clone(),
+                    span: node.span,
+                });
+            }
+        }
+        GrammarItem::Call(name) if unparsed.contains(name) => {}
+        GrammarItem::Call(name) => match functions.get(name.as_str()) {
+            None => problems.push(CompileError::UnknownFunction { name: name.clone(), span: node.span }),
+            Some(callee) if callee.arguments.len() != node.children.len() => {
+                problems.push(CompileError::WrongArgumentCount {
+                    function: name.clone(),
+                    expected: callee.arguments.len(),
+                    found: node.children.len(),
+                    span: node.span,
+                });
+            }
+            Some(_) => {}
+        },
+        _ => {}
+    }
+
+    let negated = match node.entry {
+        GrammarItem::Neg => true,
+        GrammarItem::Paren => negated,
+        _ => false,
+    };
+    for child in &node.children {
+        check_body(child, definition, functions, unparsed, negated, used, problems);
+    }
+}
+
+// Every call made by an expression, with the span of the call.
+fn calls(node: &ParseNode) -> Vec<(&str, Span)> {
+    let mut result = Vec::new();
+    if let GrammarItem::Call(name) = &node.entry {
+        result.push((name.as_str(), node.span));
+    }
+    for child in &node.children {
+        result.append(&mut calls(child));
+    }
+    result
+}
+
+// Whether `from` calls `to`, directly or through other functions.
+fn reaches<'a>(from: &'a str, to: &str, functions: &HashMap<&str, &'a Definition>, visited: &mut HashSet<&'a str>) -> bool {
+    if from == to {
+        return true;
+    }
+    if !visited.insert(from) {
+        return false;
+    }
+    match functions.get(from) {
+        Some(definition) => calls(&definition.body).into_iter()
+            .any(|(callee, _)| reaches(callee, to, functions, visited)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(problems: &[CompileError]) -> Vec<String> {
+        problems.iter()
+            .map(|problem| format!("{}:{}: {}", problem.span().formula, problem.span().start, problem))
+            .collect()
+    }
+
+    // Parse errors come first, then the problems found by validate.
+    fn problems(formulas: &[&str]) -> Vec<String> {
+        let (mut definitions, mut unparsed, mut problems) = (Vec::new(), Vec::new(), Vec::new());
+        for (i, formula) in formulas.iter().enumerate() {
+            match parse_definition(formula, i) {
+                Ok(definition) => definitions.push(definition),
+                Err(error) => {
+                    unparsed.extend(parse_header(formula, i));
+                    problems.push(error);
+                }
+            }
+        }
+        problems.append(&mut validate(&definitions, &unparsed));
+        describe(&problems)
+    }
+
+    #[test]
+    fn valid_formulas_have_no_problems() {
+        assert!(problems(&["avg(x, y) = (x + y)/2", "quad(x, a, b, c) = a*x*x + b*x + c"]).is_empty());
+        assert!(problems(&["min(x) = x - 9223372036854775807 - -9223372036854775808"]).is_empty());
+        assert!(problems(&["hyp2(a, b) = sq(a) + sq(b)", "sq(x) = x*x", "seven() = 7 + sq(3) - sq(sq(1))"]).is_empty());
+    }
+
+    #[test]
+    fn calls_are_checked_against_the_definitions() {
+        assert_eq!(problems(&["f(x) = g(x) + sq(x, 1)", "sq(x) = x*x", "a(x) = b(x)", "b(x) = a(x) + 1"]), [
+            "0:7: function `g` is not defined",
+            "0:14: `sq` takes 1 argument(s) but 2 were given",
+            "2:7: `b` ends up calling itself, which never terminates since formulas have no conditions",
+            "3:7: `a` ends up calling itself, which never terminates since formulas have no conditions",
+        ]);
+        // a callee whose body doesn't parse only has its own error
+        assert_eq!(problems(&["cube(x) = x * sq(x) + sq(x, 1)", "sq(x) = x*", "tri(x) = x $ 3", "f(x) = tri(x) + g(x)"]), [
+            "1:10: expected number, argument or '(', found end of formula",
+            "2:11: unexpected character '$'",
+            "3:16: function `g` is not defined",
+        ]);
+    }
+
+    #[test]
+    fn test_vectors_are_checked_against_the_definitions() {
+        let formulas = ["avg(x, y) = (x + y)/2", "avg(100, 80) == 90", "avg(1) == 1", "mean(1, 2) == 1", "sq(x) = x*", "sq(2) == 4"];
+        let (mut definitions, mut tests, mut unparsed) = (Vec::new(), Vec::new(), Vec::new());
+        for (i, formula) in formulas.iter().enumerate() {
+            match parse_formula(formula, i) {
+                Ok(Formula::Definition(definition)) => definitions.push(definition),
+                Ok(Formula::Test(test)) => tests.push(test),
+                Err(_) => unparsed.extend(parse_header(formula, i)),
+            }
+        }
+        // the broken `sq` has its own error, not one for every test vector of it
+        assert_eq!(describe(&validate_test_vectors(&definitions, &tests, &unparsed)), [
+            "2:0: `avg` takes 2 argument(s) but 1 were given",
+            "3:0: function `mean` is not defined",
+        ]);
+    }
+
+    #[test]
+    fn all_problems_are_reported() {
+        assert_eq!(problems(&["avg(x, y) = (x + z)/2 + w", "avg(a, b, a) = a + b", "big(x) = x + 9223372036854775808"]), [
+            "0:17: `z` is not an argument of `avg`",
+            "0:24: `w` is not an argument of `avg`",
+            "0:7: argument `y` of `avg` is never used",
+            "1:0: function `avg` is defined more than once",
+            "1:10: argument `a` of `avg` is declared more than once",
+            "2:13: number 9223372036854775808 doesn't fit in a signed 64-bit integer",
+        ]);
+    }
+}
@@ -0,0 +1,578 @@
+use crate::minimal_elf::SectionId;
+
+// Just enough of an x86-64 assembler for the code we generate:
+//
+// let mut code = Assembly::new(0);
+// let done = code.label();
+// code.mov(Reg::Rax, Mem(Reg::Rbp, 0x10));
+// code.add(Reg::Rax, Reg::Rcx);
+// code.jcc(Condition::Overflow, done);
+// code.bind(done);
+// code.ret();
+//
+// Instructions operate on 64-bit values, except for the _byte ones, movzx and
+// setcc, which read or write single bytes. The encodings pick the shortest
+// immediates and displacements. Jumps and calls always take a rel32 operand.
+
+// General purpose registers, in the order of their x86 numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Reg {
+    // The 3 bits going in ModRM or the opcode.
+    fn low(self) -> u8 {
+        self as u8 & 7
+    }
+
+    // Registers 8 and above need a REX prefix bit.
+    fn high(self) -> u8 {
+        self as u8 >> 3
+    }
+}
+
+// [base + displacement]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mem(pub Reg, pub i32);
+
+// [base + index*scale + displacement], scale being 1, 2, 4 or 8. rsp can't be an index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indexed(pub Reg, pub Reg, pub u8, pub i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Reg),
+    Mem(Mem),
+    Indexed(Indexed),
+    Imm(i64),
+}
+
+impl From<Reg> for Operand {
+    fn from(register: Reg) -> Operand {
+        Operand::Reg(register)
+    }
+}
+
+impl From<Mem> for Operand {
+    fn from(memory: Mem) -> Operand {
+        Operand::Mem(memory)
+    }
+}
+
+impl From<Indexed> for Operand {
+    fn from(memory: Indexed) -> Operand {
+        Operand::Indexed(memory)
+    }
+}
+
+impl From<i32> for Operand {
+    fn from(immediate: i32) -> Operand {
+        Operand::Imm(immediate.into())
+    }
+}
+
+impl From<i64> for Operand {
+    fn from(immediate: i64) -> Operand {
+        Operand::Imm(immediate)
+    }
+}
+
+// Conditions of jcc and setcc, by their encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Overflow,
+    NoOverflow,
+    Below,
+    AboveOrEqual,
+    Equal,
+    NotEqual,
+    BelowOrEqual,
+    Above,
+    Sign,
+    NotSign,
+    Parity,
+    NoParity,
+    Less,
+    GreaterOrEqual,
+    LessOrEqual,
+    Greater,
+}
+
+// The operations sharing the encodings of add, selected by the reg field of ModRM.
+#[derive(Clone, Copy)]
+enum Arithmetic {
+    Add = 0,
+    Or = 1,
+    And = 4,
+    Sub = 5,
+    Xor = 6,
+    Cmp = 7,
+}
+
+// The size of the operands, which decides the REX prefix.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Size {
+    // spl, bpl, sil and dil need a REX prefix to be told apart from ah, ch, dh and bh
+    Byte,
+    // no REX.W, which is still 64 bits for push and call
+    Default,
+    Quad,
+}
+
+pub type Label = usize;
+
+// rel32 operand of a jump or call ending at `end` and going to `target`.
+pub fn displacement(end: u64, target: u64) -> [u8; 4] {
+    (target.wrapping_sub(end) as u32).to_le_bytes()
+}
+
+// Machine code or data being put together before its final place is known.
+// Jumps go to labels resolved by `finish`, absolute addresses are left to the
+// ELF layout as patches.
+pub struct Assembly {
+    // offset of the first byte inside its section
+    origin: u64,
+    pub bytes: Vec<u8>,
+    labels: Vec<Option<u64>>,
+    // (offset of a rel32 operand, its label)
+    fixups: Vec<(usize, Label)>,
+    // (offset, target section, offset inside the target) of 8 byte addresses
+    pub addresses: Vec<(u64, SectionId, u64)>,
+}
+
+impl Assembly {
+    pub fn new(origin: u64) -> Assembly {
+        Assembly { origin, bytes: Vec::new(), labels: Vec::new(), fixups: Vec::new(), addresses: Vec::new() }
+    }
+
+    // Offset of the next byte inside the section.
+    pub fn position(&self) -> u64 {
+        self.origin + self.bytes.len() as u64
+    }
+
+    pub fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    // A label for code already placed at `offset` of the section, e.g. by another Assembly.
+    pub fn label_at(&mut self, offset: u64) -> Label {
+        self.labels.push(Some(offset));
+        self.labels.len() - 1
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label] = Some(self.position());
+    }
+
+    // The absolute address of `offset` inside `section`, 8 bytes wide.
+    pub fn address(&mut self, section: SectionId, offset: u64) {
+        self.addresses.push((self.position(), section, offset));
+        self.emit(&[0; 8]);
+    }
+
+    // mov <register>, address
+    pub fn load_address(&mut self, register: Reg, section: SectionId, offset: u64) {
+        self.emit(&[0x48 | register.high(), 0xb8 + register.low()]);
+        self.address(section, offset);
+    }
+
+    pub fn finish(mut self) -> Assembly {
+        for &(operand, label) in &self.fixups {
+            let target = self.labels[label].expect("jump to an unbound label");
+            let displacement = displacement(self.origin + operand as u64 + 4, target);
+            self.bytes[operand..operand + 4].copy_from_slice(&displacement);
+        }
+        self.fixups.clear();
+        self
+    }
+
+    // `opcode` followed by the rel32 operand going to `label`.
+    fn relative(&mut self, opcode: &[u8], label: Label) {
+        self.emit(opcode);
+        self.fixups.push((self.bytes.len(), label));
+        self.emit_u32(0);
+    }
+
+    // REX.W, `opcode` and a ModRM for `reg` and `rm`, which is a register or memory.
+    fn modrm(&mut self, opcode: &[u8], reg: u8, rm: Operand) {
+        self.encode(Size::Quad, opcode, reg, rm);
+    }
+
+    // The same for operands of `size`, with a REX prefix only where needed.
+    fn encode(&mut self, size: Size, opcode: &[u8], reg: u8, rm: Operand) {
+        // a SIB byte follows ModRM for indexes, and for [rsp] and [r12] which have
+        // the encoding of "SIB follows" in ModRM
+        let (base, displacement, sib) = match rm {
+            Operand::Reg(register) => (register, None, None),
+            Operand::Mem(Mem(base, displacement)) =>
+                (base, Some(displacement), (base.low() == Reg::Rsp.low()).then_some((Reg::Rsp, 1))),
+            Operand::Indexed(Indexed(base, index, scale, displacement)) => {
+                assert!(index != Reg::Rsp, "rsp can't be an index");
+                (base, Some(displacement), Some((index, scale)))
+            }
+            Operand::Imm(_) => panic!("an immediate can't be a register or memory operand"),
+        };
+        let (mode, displacement) = match displacement {
+            None => (0xc0, None),
+            // [rbp] and [r13] can only be encoded with a displacement
+            Some(0) if base.low() != Reg::Rbp.low() => (0x00, None),
+            Some(displacement) => match i8::try_from(displacement) {
+                Ok(displacement) => (0x40, Some(vec![displacement as u8])),
+                Err(_) => (0x80, Some(displacement.to_le_bytes().to_vec())),
+            },
+        };
+        let index = sib.map_or(0, |(index, _)| index.high());
+        let rex = if size == Size::Quad { 0x48 } else { 0x40 } | (reg >> 3) << 2 | index << 1 | base.high();
+        let byte_register = matches!(rm, Operand::Reg(register) if (4..8).contains(&(register as u8)));
+        if rex != 0x40 || (size == Size::Byte && byte_register) {
+            self.emit(&[rex]);
+        }
+        self.emit(opcode);
+        match sib {
+            None => self.emit(&[mode | (reg & 7) << 3 | base.low()]),
+            Some((index, scale)) => {
+                let scale = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => panic!("no scale {}", scale),
+                };
+                self.emit(&[mode | (reg & 7) << 3 | Reg::Rsp.low(), scale << 6 | index.low() << 3 | base.low()]);
+            }
+        }
+        if let Some(displacement) = displacement {
+            self.emit(&displacement);
+        }
+    }
+
+    // Only al, cl, dl, bl and r8b to r15b go in the reg field of byte instructions.
+    fn byte_register(register: Reg) -> u8 {
+        assert!(!(4..8).contains(&(register as u8)), "no encoding for the low byte of {:?} here", register);
+        register as u8
+    }
+
+    fn arithmetic(&mut self, operation: Arithmetic, destination: Operand, source: Operand) {
+        let n = operation as u8;
+        match (destination, source) {
+            (destination, Operand::Reg(source)) => self.modrm(&[8 * n + 0x01], source as u8, destination),
+            (Operand::Reg(destination), source @ (Operand::Mem(_) | Operand::Indexed(_))) =>
+                self.modrm(&[8 * n + 0x03], destination as u8, source),
+            (destination, Operand::Imm(immediate)) => match i8::try_from(immediate) {
+                Ok(immediate) => {
+                    self.modrm(&[0x83], n, destination);
+                    self.emit(&[immediate as u8]);
+                }
+                Err(_) => {
+                    let immediate = i32::try_from(immediate).expect("immediates are at most 32 bits");
+                    self.modrm(&[0x81], n, destination);
+                    self.emit(&immediate.to_le_bytes());
+                }
+            },
+            operands => panic!("no encoding for {:?}", operands),
+        }
+    }
+
+    pub fn mov(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        match (destination.into(), source.into()) {
+            (destination, Operand::Reg(source)) => self.modrm(&[0x89], source as u8, destination),
+            (Operand::Reg(destination), source @ (Operand::Mem(_) | Operand::Indexed(_))) =>
+                self.modrm(&[0x8b], destination as u8, source),
+            (destination, Operand::Imm(immediate)) => match i32::try_from(immediate) {
+                // sign extended
+                Ok(immediate) => {
+                    self.modrm(&[0xc7], 0, destination);
+                    self.emit(&immediate.to_le_bytes());
+                }
+                Err(_) => {
+                    let Operand::Reg(destination) = destination else {
+                        panic!("64-bit immediates can only be moved to registers");
+                    };
+                    self.emit(&[0x48 | destination.high(), 0xb8 + destination.low()]);
+                    self.emit(&immediate.to_le_bytes());
+                }
+            },
+            operands => panic!("no encoding for {:?}", operands),
+        }
+    }
+
+    pub fn lea(&mut self, destination: Reg, source: impl Into<Operand>) {
+        let source = source.into();
+        assert!(matches!(source, Operand::Mem(_) | Operand::Indexed(_)), "lea needs a memory operand");
+        self.modrm(&[0x8d], destination as u8, source);
+    }
+
+    // Moves the low byte of a register or an immediate to a byte of memory.
+    pub fn mov_byte(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        match (destination.into(), source.into()) {
+            (destination, Operand::Reg(source)) => self.encode(Size::Byte, &[0x88], Self::byte_register(source), destination),
+            (destination, Operand::Imm(immediate)) => {
+                self.encode(Size::Byte, &[0xc6], 0, destination);
+                self.emit(&[immediate as u8]);
+            }
+            operands => panic!("no encoding for {:?}", operands),
+        }
+    }
+
+    // Zero extends a byte of memory.
+    pub fn movzx(&mut self, destination: Reg, source: impl Into<Operand>) {
+        self.modrm(&[0x0f, 0xb6], destination as u8, source.into());
+    }
+
+    pub fn add(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::Add, destination.into(), source.into());
+    }
+
+    pub fn or(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::Or, destination.into(), source.into());
+    }
+
+    pub fn and(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::And, destination.into(), source.into());
+    }
+
+    pub fn sub(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::Sub, destination.into(), source.into());
+    }
+
+    pub fn xor(&mut self, destination: impl Into<Operand>, source: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::Xor, destination.into(), source.into());
+    }
+
+    pub fn cmp(&mut self, left: impl Into<Operand>, right: impl Into<Operand>) {
+        self.arithmetic(Arithmetic::Cmp, left.into(), right.into());
+    }
+
+    // Compares a byte of memory or the low byte of a register.
+    pub fn cmp_byte(&mut self, left: impl Into<Operand>, right: impl Into<Operand>) {
+        match (left.into(), right.into()) {
+            (left, Operand::Reg(right)) => self.encode(Size::Byte, &[0x38], Self::byte_register(right), left),
+            (left, Operand::Imm(immediate)) => {
+                self.encode(Size::Byte, &[0x80], 7, left);
+                self.emit(&[immediate as u8]);
+            }
+            operands => panic!("no encoding for {:?}", operands),
+        }
+    }
+
+    pub fn test(&mut self, left: impl Into<Operand>, right: impl Into<Operand>) {
+        match right.into() {
+            Operand::Reg(right) => self.modrm(&[0x85], right as u8, left.into()),
+            // sign extended
+            Operand::Imm(immediate) => {
+                let immediate = i32::try_from(immediate).expect("immediates are at most 32 bits");
+                self.modrm(&[0xf7], 0, left.into());
+                self.emit(&immediate.to_le_bytes());
+            }
+            right => panic!("no encoding for test with {:?}", right),
+        }
+    }
+
+    // Signed multiplication, keeping the low 64 bits. An immediate multiplies
+    // the destination itself.
+    pub fn imul(&mut self, destination: Reg, source: impl Into<Operand>) {
+        match source.into() {
+            Operand::Imm(immediate) => match i8::try_from(immediate) {
+                Ok(immediate) => {
+                    self.modrm(&[0x6b], destination as u8, destination.into());
+                    self.emit(&[immediate as u8]);
+                }
+                Err(_) => {
+                    let immediate = i32::try_from(immediate).expect("immediates are at most 32 bits");
+                    self.modrm(&[0x69], destination as u8, destination.into());
+                    self.emit(&immediate.to_le_bytes());
+                }
+            },
+            source => self.modrm(&[0x0f, 0xaf], destination as u8, source),
+        }
+    }
+
+    pub fn neg(&mut self, operand: impl Into<Operand>) {
+        self.modrm(&[0xf7], 3, operand.into());
+    }
+
+    pub fn inc(&mut self, operand: impl Into<Operand>) {
+        self.modrm(&[0xff], 0, operand.into());
+    }
+
+    pub fn dec(&mut self, operand: impl Into<Operand>) {
+        self.modrm(&[0xff], 1, operand.into());
+    }
+
+    pub fn shl(&mut self, operand: impl Into<Operand>, count: u8) {
+        self.modrm(&[0xc1], 4, operand.into());
+        self.emit(&[count]);
+    }
+
+    // Sets the low byte of `register` to 1 if `condition` holds, 0 otherwise.
+    pub fn setcc(&mut self, condition: Condition, register: Reg) {
+        self.encode(Size::Byte, &[0x0f, 0x90 + condition as u8], 0, register.into());
+    }
+
+    // Sign extends rax into rdx.
+    pub fn cqo(&mut self) {
+        self.emit(&[0x48, 0x99]);
+    }
+
+    // Divides rdx:rax, leaving the quotient in rax and the remainder in rdx.
+    pub fn idiv(&mut self, divisor: impl Into<Operand>) {
+        self.modrm(&[0xf7], 7, divisor.into());
+    }
+
+    pub fn push(&mut self, operand: impl Into<Operand>) {
+        match operand.into() {
+            Operand::Reg(register) if register.high() == 0 => self.emit(&[0x50 + register.low()]),
+            Operand::Reg(register) => self.emit(&[0x41, 0x50 + register.low()]),
+            // pushes are 64-bit without REX.W
+            memory @ (Operand::Mem(_) | Operand::Indexed(_)) => self.encode(Size::Default, &[0xff], 6, memory),
+            // sign extended
+            Operand::Imm(immediate) => match i8::try_from(immediate) {
+                Ok(immediate) => self.emit(&[0x6a, immediate as u8]),
+                Err(_) => {
+                    let immediate = i32::try_from(immediate).expect("immediates are at most 32 bits");
+                    self.emit(&[0x68]);
+                    self.emit(&immediate.to_le_bytes());
+                }
+            },
+        }
+    }
+
+    pub fn pop(&mut self, register: Reg) {
+        if register.high() != 0 {
+            self.emit(&[0x41]);
+        }
+        self.emit(&[0x58 + register.low()]);
+    }
+
+    pub fn call(&mut self, label: Label) {
+        self.relative(&[0xe8], label);
+    }
+
+    // Calls the address held in a register or memory.
+    pub fn call_indirect(&mut self, target: impl Into<Operand>) {
+        self.encode(Size::Default, &[0xff], 2, target.into());
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.relative(&[0xe9], label);
+    }
+
+    pub fn jcc(&mut self, condition: Condition, label: Label) {
+        self.relative(&[0x0f, 0x80 + condition as u8], label);
+    }
+
+    pub fn leave(&mut self) {
+        self.emit(&[0xc9]);
+    }
+
+    pub fn ret(&mut self) {
+        self.emit(&[0xc3]);
+    }
+
+    pub fn syscall(&mut self) {
+        self.emit(&[0x0f, 0x05]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(instructions: impl FnOnce(&mut Assembly)) -> Vec<u8> {
+        let mut code = Assembly::new(0);
+        instructions(&mut code);
+        code.finish().bytes
+    }
+
+    #[test]
+    fn instructions_are_encoded_with_their_prefixes_and_operands() {
+        assert_eq!(assemble(|code| code.add(Reg::Rax, Reg::Rcx)), b"\x48\x01\xc8");
+        assert_eq!(assemble(|code| code.sub(Reg::R9, Reg::R14)), b"\x4d\x29\xf1");
+        assert_eq!(assemble(|code| code.mov(Reg::Rcx, Reg::Rax)), b"\x48\x89\xc1");
+        assert_eq!(assemble(|code| code.mov(Reg::Rax, Mem(Reg::Rbp, 0x10))), b"\x48\x8b\x45\x10");
+        assert_eq!(assemble(|code| code.mov(Mem(Reg::Rbp, -8), Reg::Rdi)), b"\x48\x89\x7d\xf8");
+        assert_eq!(assemble(|code| code.mov(Mem(Reg::Rbp, -0x100), Reg::R9)), b"\x4c\x89\x8d\x00\xff\xff\xff");
+        assert_eq!(assemble(|code| code.mov(Reg::R8, Mem(Reg::R10, 0x30))), b"\x4d\x8b\x42\x30");
+        assert_eq!(assemble(|code| code.mov(Reg::Rsi, Mem(Reg::R13, 0))), b"\x49\x8b\x75\x00");
+        assert_eq!(assemble(|code| code.mov(Reg::Rax, Mem(Reg::Rsp, 8))), b"\x48\x8b\x44\x24\x08");
+        assert_eq!(assemble(|code| code.mov(Reg::R12, Mem(Reg::Rbx, 0))), b"\x4c\x8b\x23");
+        assert_eq!(assemble(|code| code.mov(Reg::Rcx, 7)), b"\x48\xc7\xc1\x07\x00\x00\x00");
+        assert_eq!(assemble(|code| code.mov(Reg::Rax, -1)), b"\x48\xc7\xc0\xff\xff\xff\xff");
+        assert_eq!(assemble(|code| code.mov(Reg::R11, i64::MIN)), b"\x49\xbb\x00\x00\x00\x00\x00\x00\x00\x80");
+        assert_eq!(assemble(|code| code.add(Reg::Rsp, 8)), b"\x48\x83\xc4\x08");
+        assert_eq!(assemble(|code| code.sub(Reg::Rsp, 0x1000)), b"\x48\x81\xec\x00\x10\x00\x00");
+        assert_eq!(assemble(|code| code.cmp(Reg::Rax, Mem(Reg::R13, 8))), b"\x49\x3b\x45\x08");
+        assert_eq!(assemble(|code| code.test(Reg::R12, Reg::R12)), b"\x4d\x85\xe4");
+        assert_eq!(assemble(|code| code.lea(Reg::Rax, Mem(Reg::R12, -2))), b"\x49\x8d\x44\x24\xfe");
+        assert_eq!(assemble(|code| code.imul(Reg::Rax, Reg::Rcx)), b"\x48\x0f\xaf\xc1");
+        assert_eq!(assemble(|code| code.neg(Reg::Rax)), b"\x48\xf7\xd8");
+        assert_eq!(assemble(|code| { code.cqo(); code.idiv(Reg::Rcx) }), b"\x48\x99\x48\xf7\xf9");
+        assert_eq!(assemble(|code| { code.push(Reg::Rbp); code.push(Reg::R15); code.pop(Reg::R9) }), b"\x55\x41\x57\x41\x59");
+        assert_eq!(assemble(|code| code.push(Mem(Reg::R10, 0x38))), b"\x41\xff\x72\x38");
+        assert_eq!(assemble(|code| code.push(Mem(Reg::Rbp, -16))), b"\xff\x75\xf0");
+        assert_eq!(assemble(|code| { code.push(-2); code.push(0x1000) }), b"\x6a\xfe\x68\x00\x10\x00\x00");
+    }
+
+    #[test]
+    fn bytes_indexes_and_immediates_have_their_own_encodings() {
+        assert_eq!(assemble(|code| code.mov(Reg::Rdi, Indexed(Reg::Rbx, Reg::R14, 8, 24))), b"\x4a\x8b\x7c\xf3\x18");
+        assert_eq!(assemble(|code| code.mov(Indexed(Reg::Rsp, Reg::R14, 8, 0), Reg::Rax)), b"\x4a\x89\x04\xf4");
+        assert_eq!(assemble(|code| code.lea(Reg::R13, Indexed(Reg::Rsi, Reg::Rax, 1, 0))), b"\x4c\x8d\x2c\x06");
+        assert_eq!(assemble(|code| code.lea(Reg::Rax, Indexed(Reg::R13, Reg::Rcx, 2, 0))), b"\x49\x8d\x44\x4d\x00");
+        assert_eq!(assemble(|code| code.cmp_byte(Indexed(Reg::Rsi, Reg::Rdx, 1, 0), 0)), b"\x80\x3c\x16\x00");
+        assert_eq!(assemble(|code| code.cmp_byte(Mem(Reg::Rsi, 0), Reg::Rax)), b"\x38\x06");
+        assert_eq!(assemble(|code| code.mov_byte(Mem(Reg::Rdi, 0), b'-' as i32)), b"\xc6\x07\x2d");
+        assert_eq!(assemble(|code| code.mov_byte(Mem(Reg::Rdi, 0), Reg::Rdx)), b"\x88\x17");
+        assert_eq!(assemble(|code| code.movzx(Reg::Rax, Mem(Reg::R12, 0))), b"\x49\x0f\xb6\x04\x24");
+        assert_eq!(assemble(|code| code.setcc(Condition::NotEqual, Reg::Rdi)), b"\x40\x0f\x95\xc7");
+        assert_eq!(assemble(|code| code.setcc(Condition::Less, Reg::Rax)), b"\x0f\x9c\xc0");
+        assert_eq!(assemble(|code| { code.inc(Reg::R12); code.dec(Reg::Rdi) }), b"\x49\xff\xc4\x48\xff\xcf");
+        assert_eq!(assemble(|code| code.shl(Reg::Rax, 3)), b"\x48\xc1\xe0\x03");
+        assert_eq!(assemble(|code| code.imul(Reg::R15, 10)), b"\x4d\x6b\xff\x0a");
+        assert_eq!(assemble(|code| code.imul(Reg::Rcx, 1000)), b"\x48\x69\xc9\xe8\x03\x00\x00");
+        assert_eq!(assemble(|code| code.test(Reg::Rbx, 8)), b"\x48\xf7\xc3\x08\x00\x00\x00");
+        assert_eq!(assemble(|code| { code.or(Reg::Rbx, 16); code.and(Reg::Rbx, -8) }), b"\x48\x83\xcb\x10\x48\x83\xe3\xf8");
+        assert_eq!(assemble(|code| code.xor(Reg::R14, Reg::R14)), b"\x4d\x31\xf6");
+        assert_eq!(assemble(|code| { code.call_indirect(Mem(Reg::R13, 16)); code.syscall() }), b"\x41\xff\x55\x10\x0f\x05");
+    }
+
+    #[test]
+    fn jumps_go_to_their_labels() {
+        let code = assemble(|code| {
+            let back = code.label();
+            let forward = code.label();
+            code.bind(back);
+            code.jcc(Condition::Overflow, forward);
+            code.jmp(back);
+            code.bind(forward);
+            let elsewhere = code.label_at(0x100);
+            code.call(elsewhere);
+        });
+        assert_eq!(code, b"\x0f\x80\x05\x00\x00\x00\xe9\xf5\xff\xff\xff\xe8\xf0\x00\x00\x00");
+    }
+}
@@ -0,0 +1,156 @@
+// Compiles random formulas to programs, runs them on random arguments and
+// compares their output with the reference interpreter behind `minicomp eval`,
+// and with the same formulas compiled in memory by `minicomp run`.
+// Set MINICOMP_SEED to reproduce a failure or explore other formulas.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const MINICOMP: &str = env!("CARGO_BIN_EXE_minicomp");
+const PROGRAMS: usize = 40;
+const RUNS_PER_FUNCTION: usize = 6;
+const SIGFPE: i32 = 8;
+
+// xorshift64*, good enough to pick formulas and arguments
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // Biased toward values where overflow, rounding and division bugs show up.
+    fn value(&mut self) -> i64 {
+        match self.below(6) {
+            0 => [0, 1, -1, 2, -2, i64::MIN, i64::MAX, i64::MIN + 1][self.below(8)],
+            1 | 2 => self.below(201) as i64 - 100,
+            3 => self.next() as i64 >> self.below(64),
+            _ => self.next() as i64,
+        }
+    }
+
+    fn literal(&mut self) -> String {
+        match self.below(4) {
+            0 => "9223372036854775807".to_string(),
+            1 => "-9223372036854775808".to_string(),
+            2 => (self.next() >> self.below(64)).min(i64::MAX as u64).to_string(),
+            _ => self.below(10).to_string(),
+        }
+    }
+}
+
+struct Function {
+    name: String,
+    arguments: Vec<String>,
+}
+
+// An expression over `arguments` that may call the functions defined before.
+fn expression(random: &mut Random, arguments: &[String], callees: &[Function], depth: usize) -> String {
+    let leaf = depth == 0 || random.below(4) == 0;
+    if leaf {
+        return if !arguments.is_empty() && random.below(3) > 0 {
+            arguments[random.below(arguments.len())].clone()
+        } else {
+            random.literal()
+        };
+    }
+    match random.below(10) {
+        0 => format!("-{}", expression(random, arguments, callees, depth - 1)),
+        1 => format!("({})", expression(random, arguments, callees, depth - 1)),
+        2 if !callees.is_empty() => {
+            let callee = &callees[random.below(callees.len())];
+            let values = (0..callee.arguments.len())
+                .map(|_| expression(random, arguments, callees, depth - 1))
+                .collect::<Vec<_>>();
+            format!("{}({})", callee.name, values.join(", "))
+        }
+        _ => {
+            let operator = ["+", "-", "*", "/", "%", "+", "-", "*"][random.below(8)];
+            format!("{} {} {}",
+                    expression(random, arguments, callees, depth - 1),
+                    operator,
+                    expression(random, arguments, callees, depth - 1))
+        }
+    }
+}
+
+// A few functions, each able to call the ones before it so there's no recursion.
+fn program(random: &mut Random) -> (String, Vec<Function>) {
+    let mut functions: Vec<Function> = Vec::new();
+    let mut formulas = Vec::new();
+    for i in 0..1 + random.below(4) {
+        let arguments = (0..random.below(9)).map(|j| format!("a{}", j)).collect::<Vec<_>>();
+        // deep enough for more values to be live at once than there are registers
+        let depth = 1 + random.below(8);
+        let body = expression(random, &arguments, &functions, depth);
+        let name = format!("f{}", i);
+        formulas.push(format!("{}({}) = {}", name, arguments.join(", "), body));
+        functions.push(Function { name, arguments });
+    }
+    (formulas.join("; "), functions)
+}
+
+fn run(command: &Path, arguments: &[String]) -> Output {
+    Command::new(command).args(arguments).output()
+        .unwrap_or_else(|error| panic!("failed to run {}: {}", command.display(), error))
+}
+
+fn scratch_directory() -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("minicomp-differential-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn compiled_programs_agree_with_the_interpreter() {
+    let seed = std::env::var("MINICOMP_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or(0x5eed_1234_abcd_0001);
+    let mut random = Random(seed.max(1)); // a zero state stays zero
+    let directory = scratch_directory();
+    let executable = directory.join("program.elf");
+
+    for _ in 0..PROGRAMS {
+        let (source, functions) = program(&mut random);
+        let compiled = run(Path::new(MINICOMP), &[executable.display().to_string(), source.clone()]);
+        assert!(compiled.status.success(), "failed to compile {}\n{}", source, String::from_utf8_lossy(&compiled.stderr));
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+
+        for function in &functions {
+            for _ in 0..RUNS_PER_FUNCTION {
+                let mut arguments = vec![function.name.clone()];
+                arguments.extend((0..function.arguments.len()).map(|_| random.value().to_string()));
+
+                let subcommand = |name: &str| {
+                    let mut subcommand_arguments = vec![String::from(name), source.clone()];
+                    subcommand_arguments.extend(arguments.iter().cloned());
+                    run(Path::new(MINICOMP), &subcommand_arguments)
+                };
+                let reference = subcommand("eval");
+
+                let context = format!("seed {}\nsource: {}\narguments: {:?}", seed, source, arguments);
+                for (kind, output) in [("program", run(&executable, &arguments)), ("JIT", subcommand("run"))] {
+                    if reference.status.success() {
+                        assert!(output.status.success(), "{}\nthe {} failed: {:?}", context, kind, output.status);
+                        assert_eq!(String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&reference.stdout),
+                                   "{}\n{}", context, kind);
+                    } else {
+                        // the only run time errors are divisions the processor refuses to do
+                        assert_eq!(output.status.signal(), Some(SIGFPE), "{}\n{}\ninterpreter: {}", context, kind,
+                                   String::from_utf8_lossy(&reference.stderr));
+                    }
+                }
+            }
+        }
+    }
+
+    fs::remove_dir_all(&directory).unwrap();
+}